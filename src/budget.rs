@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+
+// Stops run when too many tasks fail. Only final task outcomes are counted, not retried attempts
+pub struct ErrorBudget {
+    fail_fast: bool,
+    // 0 means no limit
+    max_errors: u64,
+    // 0 means no limit
+    max_error_rate: f64,
+    window: usize,
+    errors: u64,
+    // outcomes of last finished tasks, true if task failed
+    recent: VecDeque<bool>
+}
+
+impl ErrorBudget {
+
+    pub fn new(fail_fast: bool, max_errors: u64, max_error_rate: f64, window: usize) -> ErrorBudget {
+        ErrorBudget {
+            fail_fast,
+            max_errors,
+            max_error_rate,
+            window: window.max(1),
+            errors: 0,
+            recent: VecDeque::new()
+        }
+    }
+
+    // Returns reason to stop the run if budget is exceeded
+    pub fn record(&mut self, task_id: &str, failed: bool) -> Option<String> {
+        if self.max_error_rate > 0.0 {
+            self.recent.push_back(failed);
+
+            if self.recent.len() > self.window {
+                self.recent.pop_front();
+            }
+        }
+
+        if !failed {
+            return None;
+        }
+
+        self.errors += 1;
+
+        if self.fail_fast {
+            return Some(format!("Task {} failed and fail fast is enabled", task_id));
+        }
+
+        if self.max_errors > 0 && self.errors >= self.max_errors {
+            return Some(format!("{} tasks failed, error limit is {}", self.errors, self.max_errors));
+        }
+
+        // rate is not reliable until window is full
+        if self.max_error_rate > 0.0 && self.recent.len() == self.window {
+            let rate = self.recent.iter().filter(|failed| **failed).count() as f64 / self.window as f64;
+
+            if rate >= self.max_error_rate {
+                return Some(format!(
+                    "{:.0}% of last {} tasks failed, error rate limit is {:.0}%",
+                    rate * 100.0, self.window, self.max_error_rate * 100.0
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+// parse error rate given as percent or fraction, e.g. "20%" or "0.2"
+pub fn parse_error_rate(value: &str) -> anyhow::Result<f64> {
+    let rate = match value.trim().strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>()? / 100.0,
+        None => value.trim().parse::<f64>()?
+    };
+
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(anyhow::anyhow!("Error rate must be between 0% and 100%"));
+    }
+
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // index of outcome which exceeded budget
+    fn exceeded_at(budget: &mut ErrorBudget, outcomes: &[bool]) -> Option<usize> {
+        outcomes.iter().enumerate().position(|(idx, failed)| budget.record(&idx.to_string(), *failed).is_some())
+    }
+
+    #[test]
+    fn unlimited_budget_is_never_exceeded() {
+        let mut budget = ErrorBudget::new(false, 0, 0.0, 100);
+        assert_eq!(exceeded_at(&mut budget, &[true; 1000]), None);
+    }
+
+    #[test]
+    fn fail_fast_stops_on_first_failure() {
+        let mut budget = ErrorBudget::new(true, 0, 0.0, 100);
+
+        assert_eq!(budget.record("a", false), None);
+        assert_eq!(budget.record("b", true), Some("Task b failed and fail fast is enabled".to_owned()));
+    }
+
+    #[test]
+    fn errors_are_counted_up_to_limit() {
+        let mut budget = ErrorBudget::new(false, 3, 0.0, 100);
+
+        assert_eq!(exceeded_at(&mut budget, &[true, false, true, false, false]), None);
+        assert_eq!(budget.record("x", true), Some("3 tasks failed, error limit is 3".to_owned()));
+    }
+
+    #[test]
+    fn error_rate_is_checked_when_window_is_full() {
+        let mut budget = ErrorBudget::new(false, 0, 0.5, 4);
+        // successful task does not stop the run even if rate is exceeded
+        assert_eq!(exceeded_at(&mut budget, &[true, true, true, false, true]), Some(4));
+
+        let mut budget = ErrorBudget::new(false, 0, 0.5, 4);
+        assert_eq!(budget.record("a", true), None);
+        assert_eq!(budget.record("b", true), None);
+        assert_eq!(budget.record("c", true), None);
+        assert_eq!(budget.record("d", true), Some("100% of last 4 tasks failed, error rate limit is 50%".to_owned()));
+    }
+
+    #[test]
+    fn error_rate_uses_last_tasks_only() {
+        let mut budget = ErrorBudget::new(false, 0, 0.5, 4);
+
+        assert_eq!(exceeded_at(&mut budget, &[true, false, false, false]), None);
+        // window is false, false, false, true
+        assert_eq!(budget.record("e", true), None);
+        // window is false, false, true, true
+        assert_eq!(budget.record("f", true), Some("50% of last 4 tasks failed, error rate limit is 50%".to_owned()));
+    }
+
+    #[test]
+    fn error_rate_is_parsed() {
+        assert_eq!(parse_error_rate("20%").unwrap(), 0.2);
+        assert_eq!(parse_error_rate(" 5 % ").unwrap(), 0.05);
+        assert_eq!(parse_error_rate("0.25").unwrap(), 0.25);
+        assert_eq!(parse_error_rate("100%").unwrap(), 1.0);
+        assert_eq!(parse_error_rate("0").unwrap(), 0.0);
+
+        assert!(parse_error_rate("120%").is_err());
+        assert!(parse_error_rate("1.5").is_err());
+        assert!(parse_error_rate("-1%").is_err());
+        assert!(parse_error_rate("many").is_err());
+    }
+}
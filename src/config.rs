@@ -0,0 +1,402 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::ArgMatches;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::budget::{self, ErrorBudget};
+use crate::executor::OutputLimits;
+use crate::input::InputFormat;
+use crate::limits::Slots;
+use crate::pipeline::{self, Stage};
+use crate::retry::{RetryPolicy, RetryPolicyKind, RetryRules};
+use crate::success::{JsonFieldCheck, SuccessCriteria};
+use crate::shutdown::ShutdownMode;
+
+// Settings of process subcommand. Stored in database, so run can be resumed later
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunConfig {
+    pub workers: usize,
+    pub tries: u32,
+    pub retry_delay: u32,
+    pub retry_policy: RetryPolicyKind,
+    pub retry_max_delay: u32,
+    pub retry_jitter: f64,
+    pub retry_on_exit: Vec<i32>,
+    pub no_retry_on_exit: Vec<i32>,
+    pub retry_if_stderr_matches: Option<String>,
+    pub success_exit_codes: Vec<i32>,
+    pub success_if_stdout_matches: Option<String>,
+    pub fail_if_stderr_matches: Option<String>,
+    pub success_json_field: Option<String>,
+    pub fail_fast: bool,
+    pub max_errors: u64,
+    pub max_error_rate: f64,
+    pub error_rate_window: usize,
+    pub timeout: u32,
+    pub max_output_size: usize,
+    pub output_dir: Option<String>,
+    pub shutdown_mode: ShutdownMode,
+    pub grace_period: u64,
+    pub exec: Option<String>,
+    // run commands directly instead of sh -c
+    pub argv: bool,
+    // pipeline stages, exec is not used if present
+    pub stages: Vec<Stage>,
+    // detected from tasks file extension if not set
+    pub format: Option<InputFormat>,
+    // JSON pointer of task id in JSON input
+    pub id_field: String,
+    pub delimeter: String,
+    pub has_header: bool,
+    pub timeout_column: Option<usize>,
+    pub priority: i32,
+    pub priority_column: Option<usize>,
+    pub depends_column: Option<usize>,
+    pub group_column: Option<usize>,
+    // maximum number of running tasks of given group
+    pub group_limits: BTreeMap<String, usize>,
+    // limit of groups without own limit
+    pub default_group_limit: Option<usize>
+}
+
+impl Default for RunConfig {
+
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            tries: 0,
+            retry_delay: 1,
+            retry_policy: RetryPolicyKind::Fixed,
+            retry_max_delay: 0,
+            retry_jitter: 0.0,
+            retry_on_exit: vec![],
+            no_retry_on_exit: vec![],
+            retry_if_stderr_matches: None,
+            success_exit_codes: vec![0],
+            success_if_stdout_matches: None,
+            fail_if_stderr_matches: None,
+            success_json_field: None,
+            fail_fast: false,
+            max_errors: 0,
+            max_error_rate: 0.0,
+            error_rate_window: 100,
+            timeout: 0,
+            max_output_size: 1024 * 1024,
+            output_dir: None,
+            shutdown_mode: ShutdownMode::Terminate,
+            grace_period: 10,
+            exec: None,
+            argv: false,
+            stages: vec![],
+            format: None,
+            id_field: String::from("/id"),
+            delimeter: String::from(","),
+            has_header: false,
+            timeout_column: None,
+            priority: 0,
+            priority_column: None,
+            depends_column: None,
+            group_column: None,
+            group_limits: BTreeMap::new(),
+            default_group_limit: None
+        }
+    }
+}
+
+impl RunConfig {
+
+    // override settings with arguments present in command line
+    pub fn apply_matches(&mut self, matches: &ArgMatches) -> anyhow::Result<()> {
+        if matches.value_of("workers").is_some() {
+            self.workers = matches.value_of_t("workers")?;
+        }
+
+        if matches.value_of("tries").is_some() {
+            self.tries = matches.value_of_t("tries")?;
+        }
+
+        if matches.value_of("delay").is_some() {
+            self.retry_delay = matches.value_of_t("delay")?;
+        }
+
+        if matches.value_of("retry-policy").is_some() {
+            self.retry_policy = matches.value_of_t("retry-policy")?;
+        }
+
+        if matches.value_of("retry-max-delay").is_some() {
+            self.retry_max_delay = matches.value_of_t("retry-max-delay")?;
+        }
+
+        if matches.value_of("retry-jitter").is_some() {
+            self.retry_jitter = matches.value_of_t("retry-jitter")?;
+
+            if !(0.0..=1.0).contains(&self.retry_jitter) {
+                return Err(anyhow::anyhow!("Retry jitter must be between 0 and 1"));
+            }
+        }
+
+        if let Some(codes) = matches.value_of("retry-on-exit") {
+            self.retry_on_exit = parse_exit_codes(codes)?;
+        }
+
+        if let Some(codes) = matches.value_of("no-retry-on-exit") {
+            self.no_retry_on_exit = parse_exit_codes(codes)?;
+        }
+
+        if let Some(pattern) = matches.value_of("retry-if-stderr-matches") {
+            Regex::new(pattern)?;
+            self.retry_if_stderr_matches = Some(pattern.to_owned());
+        }
+
+        if let Some(codes) = matches.value_of("success-exit-codes") {
+            self.success_exit_codes = parse_exit_codes(codes)?;
+        }
+
+        if let Some(pattern) = matches.value_of("success-if-stdout-matches") {
+            Regex::new(pattern)?;
+            self.success_if_stdout_matches = Some(pattern.to_owned());
+        }
+
+        if let Some(pattern) = matches.value_of("fail-if-stderr-matches") {
+            Regex::new(pattern)?;
+            self.fail_if_stderr_matches = Some(pattern.to_owned());
+        }
+
+        if let Some(field) = matches.value_of("success-json-field") {
+            JsonFieldCheck::from_str(field)?;
+            self.success_json_field = Some(field.to_owned());
+        }
+
+        if matches.is_present("fail-fast") {
+            self.fail_fast = true;
+        }
+
+        if matches.value_of("max-errors").is_some() {
+            self.max_errors = matches.value_of_t("max-errors")?;
+        }
+
+        if let Some(rate) = matches.value_of("max-error-rate") {
+            self.max_error_rate = budget::parse_error_rate(rate)?;
+        }
+
+        if matches.value_of("error-rate-window").is_some() {
+            self.error_rate_window = matches.value_of_t("error-rate-window")?;
+        }
+
+        if matches.value_of("timeout").is_some() {
+            self.timeout = matches.value_of_t("timeout")?;
+        }
+
+        if let Some(size) = matches.value_of("max-output-size") {
+            self.max_output_size = parse_size(size)?;
+        }
+
+        if let Some(dir) = matches.value_of("output-dir") {
+            self.output_dir = Some(dir.to_owned());
+        }
+
+        if matches.value_of("timeout-column").is_some() {
+            self.timeout_column = Some(matches.value_of_t("timeout-column")?);
+        }
+
+        if matches.value_of("priority").is_some() {
+            self.priority = matches.value_of_t("priority")?;
+        }
+
+        if matches.value_of("priority-column").is_some() {
+            self.priority_column = Some(matches.value_of_t("priority-column")?);
+        }
+
+        if matches.value_of("depends-column").is_some() {
+            self.depends_column = Some(matches.value_of_t("depends-column")?);
+        }
+
+        if matches.value_of("group-column").is_some() {
+            self.group_column = Some(matches.value_of_t("group-column")?);
+        }
+
+        if let Some(limits) = matches.values_of("group-limit") {
+            self.group_limits.clear();
+            self.default_group_limit = None;
+
+            for limit in limits {
+                let (group, value) = match limit.rsplit_once('=') {
+                    Some((group, value)) => (Some(group), value),
+                    None => (None, limit)
+                };
+
+                let value: usize = value.trim().parse().map_err(|_| anyhow::anyhow!("Invalid group limit: {}", limit))?;
+
+                if value == 0 {
+                    return Err(anyhow::anyhow!("Group limit must be at least 1: {}", limit));
+                }
+
+                match group {
+                    Some(group) => { self.group_limits.insert(group.to_owned(), value); },
+                    None => self.default_group_limit = Some(value)
+                }
+            }
+        }
+
+        if matches.value_of("shutdown-mode").is_some() {
+            self.shutdown_mode = matches.value_of_t("shutdown-mode")?;
+        }
+
+        if matches.value_of("grace-period").is_some() {
+            self.grace_period = matches.value_of_t("grace-period")?;
+        }
+
+        if let Some(exec) = matches.value_of("exec") {
+            self.exec = Some(exec.to_owned());
+            self.stages.clear();
+        }
+
+        if matches.is_present("argv") {
+            self.argv = true;
+        }
+
+        if let Some(path) = matches.value_of("pipeline") {
+            self.stages = pipeline::load_stages(path)?;
+        }
+
+        if matches.value_of("format").is_some() {
+            self.format = Some(matches.value_of_t("format")?);
+        }
+
+        if let Some(field) = matches.value_of("id-field") {
+            if !field.starts_with('/') {
+                return Err(anyhow::anyhow!("Id field must be JSON pointer starting with '/': {}", field));
+            }
+
+            self.id_field = field.to_owned();
+        }
+
+        if let Some(delimeter) = matches.value_of("delimeter") {
+            if delimeter.is_empty() {
+                return Err(anyhow::anyhow!("CSV delimeter can not be empty"));
+            }
+
+            self.delimeter = delimeter.to_owned();
+        }
+
+        if matches.is_present("has-header") {
+            self.has_header = true;
+        }
+
+        Ok(())
+    }
+
+    // command template of each pipeline stage, single --exec is pipeline with one stage
+    pub fn command_templates(&self) -> Vec<&str> {
+        if self.stages.is_empty() {
+            self.exec.as_deref().into_iter().collect()
+        } else {
+            self.stages.iter().map(|stage| stage.exec.as_str()).collect()
+        }
+    }
+
+    pub fn stage_tries(&self, stage: usize) -> u32 {
+        self.stages.get(stage).and_then(|stage| stage.tries).unwrap_or(self.tries)
+    }
+
+    pub fn stage_timeout(&self, stage: usize) -> u32 {
+        self.stages.get(stage).and_then(|stage| stage.timeout).unwrap_or(self.timeout)
+    }
+
+    pub fn max_tries(&self) -> u32 {
+        self.stages.iter().filter_map(|stage| stage.tries).fold(self.tries, u32::max)
+    }
+
+    pub fn group_slots(&self) -> Slots<String> {
+        let limits: HashMap<String, usize> = self.group_limits.iter().map(|(group, limit)| (group.clone(), *limit)).collect();
+        Slots::new(limits, self.default_group_limit)
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            kind: self.retry_policy,
+            delay: self.retry_delay,
+            max_delay: self.retry_max_delay,
+            jitter: self.retry_jitter
+        }
+    }
+
+    pub fn retry_rules(&self) -> anyhow::Result<RetryRules> {
+        Ok(RetryRules {
+            retry_on_exit: self.retry_on_exit.clone(),
+            no_retry_on_exit: self.no_retry_on_exit.clone(),
+            stderr_pattern: self.retry_if_stderr_matches.as_deref().map(Regex::new).transpose()?
+        })
+    }
+
+    pub fn error_budget(&self) -> ErrorBudget {
+        ErrorBudget::new(self.fail_fast, self.max_errors, self.max_error_rate, self.error_rate_window)
+    }
+
+    pub fn output_limits(&self) -> OutputLimits {
+        OutputLimits {
+            max_size: self.max_output_size,
+            spool_dir: self.output_dir.as_ref().map(PathBuf::from)
+        }
+    }
+
+    pub fn success_criteria(&self) -> anyhow::Result<SuccessCriteria> {
+        Ok(SuccessCriteria {
+            exit_codes: self.success_exit_codes.clone(),
+            stdout_pattern: self.success_if_stdout_matches.as_deref().map(Regex::new).transpose()?,
+            stderr_pattern: self.fail_if_stderr_matches.as_deref().map(Regex::new).transpose()?,
+            json_field: self.success_json_field.as_deref().map(JsonFieldCheck::from_str).transpose()?
+        })
+    }
+}
+
+// parse comma separated list of exit codes, e.g. "75,111"
+fn parse_exit_codes(value: &str) -> anyhow::Result<Vec<i32>> {
+    value.split(',')
+        .map(|code| code.trim())
+        .filter(|code| !code.is_empty())
+        .map(|code| code.parse().map_err(|_| anyhow::anyhow!("Invalid exit code: {}", code)))
+        .collect()
+}
+
+// parse number of bytes with optional K, M or G suffix, e.g. "512K"
+fn parse_size(value: &str) -> anyhow::Result<usize> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&value[..value.len() - 1], 1024),
+        Some('M') => (&value[..value.len() - 1], 1024 * 1024),
+        Some('G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1)
+    };
+
+    let number: usize = number.trim().parse().map_err(|_| anyhow::anyhow!("Invalid size: {}", value))?;
+
+    number.checked_mul(multiplier).ok_or_else(|| anyhow::anyhow!("Size is too big: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_are_parsed_with_units() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("512K").unwrap(), 512 * 1024);
+        assert_eq!(parse_size(" 10m ").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("1 G").unwrap(), 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn invalid_sizes_are_rejected() {
+        assert_eq!(parse_size("10MB").unwrap_err().to_string(), "Invalid size: 10MB");
+        assert_eq!(parse_size("K").unwrap_err().to_string(), "Invalid size: K");
+        assert_eq!(parse_size("-1").unwrap_err().to_string(), "Invalid size: -1");
+        assert_eq!(parse_size("").unwrap_err().to_string(), "Invalid size: ");
+        assert!(parse_size(&format!("{}G", usize::MAX)).unwrap_err().to_string().starts_with("Size is too big"));
+    }
+}
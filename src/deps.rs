@@ -0,0 +1,88 @@
+use std::collections::{HashMap, VecDeque};
+
+// Returns tasks which form a dependency cycle, edges are (task, task it depends on)
+pub fn find_cycle(edges: &[(String, String)]) -> Option<Vec<String>> {
+    let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut children_count: HashMap<&str, usize> = HashMap::new();
+
+    for (task, parent) in edges {
+        parents.entry(task.as_str()).or_default().push(parent.as_str());
+        parents.entry(parent.as_str()).or_default();
+        children.entry(parent.as_str()).or_default().push(task.as_str());
+        *children_count.entry(parent.as_str()).or_default() += 1;
+    }
+
+    // remove tasks nobody depends on until only cycles are left (Kahn's algorithm on reversed graph)
+    let mut queue: VecDeque<&str> = parents.keys().filter(|task| !children_count.contains_key(*task)).copied().collect();
+
+    while let Some(task) = queue.pop_front() {
+        for parent in &parents[task] {
+            let count = children_count.get_mut(parent).unwrap();
+            *count -= 1;
+
+            if *count == 0 {
+                queue.push_back(parent);
+            }
+        }
+    }
+
+    let remaining: Vec<&str> = children_count.iter().filter(|(_, count)| **count > 0).map(|(task, _)| *task).collect();
+    let start = *remaining.iter().min()?;
+
+    // every remaining task has remaining child, so walking children inside remaining part of graph ends in a cycle
+    let mut path = vec![start];
+
+    loop {
+        let current = *path.last().unwrap();
+        let next = *children[current].iter().find(|child| children_count.get(*child).is_some_and(|count| *count > 0)).unwrap();
+
+        if let Some(pos) = path.iter().position(|task| *task == next) {
+            // path goes from parents to children, show it as "task depends on task"
+            let mut cycle: Vec<String> = path[pos..].iter().rev().map(|task| task.to_string()).collect();
+            cycle.insert(0, next.to_string());
+            return Some(cycle);
+        }
+
+        path.push(next);
+    }
+}
+
+// parse list of task ids separated by ';', e.g. "build;lint"
+pub fn parse_dependencies(value: &str) -> Vec<&str> {
+    value.split(';').map(str::trim).filter(|task| !task.is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter().map(|(task, parent)| (task.to_string(), parent.to_string())).collect()
+    }
+
+    #[test]
+    fn finds_self_dependency() {
+        assert_eq!(find_cycle(&edges(&[("a", "a")])), Some(vec!["a".to_owned(), "a".to_owned()]));
+    }
+
+    #[test]
+    fn finds_cycle_with_ancestor_and_descendant_outside_of_it() {
+        // 0 is ancestor of cycle (and the first task in order), d depends on cycle
+        let cycle = find_cycle(&edges(&[("a", "b"), ("b", "c"), ("c", "a"), ("a", "0"), ("d", "a")])).unwrap();
+
+        assert_eq!(cycle, vec!["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn accepts_acyclic_diamond() {
+        assert_eq!(find_cycle(&edges(&[("b", "a"), ("c", "a"), ("d", "b"), ("d", "c")])), None);
+        assert_eq!(find_cycle(&[]), None);
+    }
+
+    #[test]
+    fn parses_dependencies_list() {
+        assert_eq!(parse_dependencies(" a; b ;;"), vec!["a", "b"]);
+        assert!(parse_dependencies("").is_empty());
+    }
+}
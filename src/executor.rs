@@ -0,0 +1,525 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::shell;
+
+// how often running child is polled for exit status
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// how long output pipes can stay open after command exited or was killed. Processes left in background which still
+// write to them are killed after that, and output of processes which can not be killed (e.g. started new session) is not waited for
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+// number of last output bytes of running task available for follow pane
+const LIVE_OUTPUT_SIZE: usize = 64 * 1024;
+
+static NEXT_WORKER_ID: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    // pool threads live until pool is dropped, so each of them gets stable id
+    static WORKER_ID: usize = NEXT_WORKER_ID.fetch_add(1, Ordering::SeqCst);
+}
+
+// id of current pool thread
+pub fn worker_id() -> usize {
+    WORKER_ID.with(|id| *id)
+}
+
+// How much of command output is kept in memory and database
+#[derive(Clone, Default)]
+pub struct OutputLimits {
+    // maximum number of bytes kept per stream, 0 means no limit
+    pub max_size: usize,
+    // directory where full output is written to while task is running
+    pub spool_dir: Option<PathBuf>
+}
+
+// In argv mode command is split into words and executed directly, without shell. Stage is name of pipeline stage, if pipeline is used
+pub fn execute_command(command_str: &str, task_id: &str, stage: Option<&str>, argv: bool, timeout: Option<Duration>, limits: &OutputLimits, running: &RunningTasks) -> ExecCommandResult {
+    let mut command = if argv {
+        let words = match shell::split(command_str) {
+            Ok(words) if !words.is_empty() => words,
+            Ok(_) => return spawn_error_result(command_str, task_id, "Command is empty"),
+            Err(err) => return spawn_error_result(command_str, task_id, &err.to_string())
+        };
+
+        let mut command = process::Command::new(&words[0]);
+        command.args(&words[1..]);
+        command
+    } else {
+        let mut command = process::Command::new("sh");
+        command.arg("-c");
+        command.arg(command_str);
+        command
+    };
+
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+
+    // run command in its own process group, so we can kill sh and everything it spawned
+    command.process_group(0);
+
+    let started_at = epoch_seconds();
+    let now = Instant::now();
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => return spawn_error_result(command_str, task_id, &format!("Can not spawn command: {}", err))
+    };
+    let live_output = running.register(task_id, child.id());
+
+    let stdout_path = spool_path(limits, task_id, stage, "out");
+    let stderr_path = spool_path(limits, task_id, stage, "err");
+
+    // read pipes in separate threads, otherwise child can block on full pipe buffer
+    let stdout_reader = spawn_reader(child.stdout.take().unwrap(), limits.max_size, stdout_path.clone(), live_output.clone());
+    let stderr_reader = spawn_reader(child.stderr.take().unwrap(), limits.max_size, stderr_path.clone(), live_output);
+
+    let mut timed_out = false;
+    let mut exit_status = None;
+    let mut exited_at: Option<Instant> = None;
+    let mut killed_at: Option<Instant> = None;
+
+    // command is finished when it exited and its output is read to the end. Detached background processes
+    // do not matter, but the ones which keep pipes open would make worker wait for them
+    loop {
+        if exit_status.is_none() {
+            exit_status = child.try_wait().expect("Can not get command status");
+
+            if exit_status.is_some() {
+                exited_at = Some(Instant::now());
+            }
+        }
+
+        let output_read = stdout_reader.is_finished() && stderr_reader.is_finished();
+
+        if exit_status.is_some() && output_read {
+            break;
+        }
+
+        match killed_at {
+            Some(killed_at) => {
+                if exit_status.is_some() && killed_at.elapsed() >= OUTPUT_GRACE_PERIOD {
+                    break;
+                }
+            },
+            None => {
+                if timeout.is_some_and(|timeout| now.elapsed() >= timeout) {
+                    timed_out = true;
+                    killed_at = Some(Instant::now());
+                    kill_process_group(child.id(), libc::SIGKILL);
+                } else if exited_at.is_some_and(|exited_at| exited_at.elapsed() >= OUTPUT_GRACE_PERIOD) {
+                    killed_at = Some(Instant::now());
+                    kill_process_group(child.id(), libc::SIGKILL);
+                }
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let exit_status = exit_status.unwrap();
+
+    let elapsed_time_ms = now.elapsed().as_millis();
+    let aborted = running.unregister(task_id);
+
+    ExecCommandResult {
+        task_id: task_id.to_owned(),
+        exit_status,
+        timed_out,
+        aborted,
+        command: command_str.to_owned(),
+        stdout: stdout_reader.output(),
+        stderr: stderr_reader.output(),
+        stdout_path: stdout_path.map(|path| path.to_string_lossy().into_owned()),
+        stderr_path: stderr_path.map(|path| path.to_string_lossy().into_owned()),
+        elapsed_time_ms,
+        started_at,
+        finished_at: epoch_seconds(),
+        worker_id: worker_id()
+    }
+}
+
+// command which could not be started fails with exit code 127, like in shell
+pub fn spawn_error_result(command_str: &str, task_id: &str, error: &str) -> ExecCommandResult {
+    let now = epoch_seconds();
+
+    ExecCommandResult {
+        task_id: task_id.to_owned(),
+        exit_status: process::ExitStatus::from_raw(127 << 8),
+        timed_out: false,
+        aborted: false,
+        command: command_str.to_owned(),
+        stdout: String::new(),
+        stderr: error.to_owned(),
+        stdout_path: None,
+        stderr_path: None,
+        elapsed_time_ms: 0,
+        started_at: now,
+        finished_at: now,
+        worker_id: worker_id()
+    }
+}
+
+fn epoch_seconds() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+pub fn kill_process_group(pgid: u32, signal: libc::c_int) {
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), signal);
+    }
+}
+
+// Output of command read by separate thread. Output can be taken before stream is closed,
+// reader thread stops on next read then
+struct OutputReader {
+    handle: JoinHandle<()>,
+    output: Arc<Mutex<Option<CappedOutput>>>
+}
+
+impl OutputReader {
+
+    fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    // Invalid UTF-8 is replaced
+    fn output(self) -> String {
+        self.output.lock().unwrap().take().map(CappedOutput::into_string).unwrap_or_default()
+    }
+}
+
+// Reads stream to the end as data arrives, keeping at most max_size bytes (head and tail) in memory.
+// Full output is written to spool file if given
+fn spawn_reader(mut source: impl Read + Send + 'static, max_size: usize, spool_path: Option<PathBuf>, live_output: LiveOutput) -> OutputReader {
+    let output = Arc::new(Mutex::new(Some(CappedOutput::new(max_size))));
+    let reader_output = output.clone();
+
+    let handle = thread::spawn(move || {
+        // not buffered, so file can be followed with tail -f
+        let mut spool = spool_path.and_then(|path| File::create(path).ok());
+        let mut buf = [0u8; 64 * 1024];
+
+        loop {
+            let n = match source.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break
+            };
+
+            if let Some(file) = spool.as_mut() {
+                let _ = file.write_all(&buf[..n]);
+            }
+
+            live_output.push(&buf[..n]);
+
+            match reader_output.lock().unwrap().as_mut() {
+                Some(output) => output.push(&buf[..n]),
+                None => break
+            }
+        }
+    });
+
+    OutputReader { handle, output }
+}
+
+// full output of task is spooled to <dir>/<task id>.out and <dir>/<task id>.err (<task id>.<stage>.out for pipeline stages),
+// task id and stage are escaped to be a valid file name
+fn spool_path(limits: &OutputLimits, task_id: &str, stage: Option<&str>, stream: &str) -> Option<PathBuf> {
+    let dir = limits.spool_dir.as_deref()?;
+
+    let name = match stage {
+        Some(stage) => format!("{}.{}.{}", escape_file_name(task_id), escape_file_name(stage), stream),
+        None => format!("{}.{}", escape_file_name(task_id), stream)
+    };
+
+    Some(Path::new(dir).join(name))
+}
+
+fn escape_file_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || (b == b'.' && !escaped.is_empty()) {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", b));
+        }
+    }
+
+    escaped
+}
+
+struct CappedOutput {
+    max_size: usize,
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    total: usize
+}
+
+impl CappedOutput {
+
+    fn new(max_size: usize) -> CappedOutput {
+        CappedOutput { max_size, head: vec![], tail: VecDeque::new(), total: 0 }
+    }
+
+    fn push(&mut self, mut data: &[u8]) {
+        self.total += data.len();
+
+        if self.max_size == 0 {
+            self.head.extend_from_slice(data);
+            return;
+        }
+
+        let head_size = self.max_size / 2;
+        let tail_size = self.max_size - head_size;
+
+        if self.head.len() < head_size {
+            let n = data.len().min(head_size - self.head.len());
+            self.head.extend_from_slice(&data[..n]);
+            data = &data[n..];
+        }
+
+        if data.len() >= tail_size {
+            self.tail.clear();
+            data = &data[data.len() - tail_size..];
+        } else {
+            let overflow = (self.tail.len() + data.len()).saturating_sub(tail_size);
+            self.tail.drain(..overflow);
+        }
+
+        self.tail.extend(data);
+    }
+
+    fn into_string(self) -> String {
+        let kept = self.head.len() + self.tail.len();
+        let mut output = String::from_utf8_lossy(&self.head).into_owned();
+
+        if self.total > kept {
+            output.push_str(&format!("\n[... {} bytes truncated ...]\n", self.total - kept));
+        }
+
+        let (a, b) = self.tail.as_slices();
+        output.push_str(&String::from_utf8_lossy(&[a, b].concat()));
+        output
+    }
+}
+
+// Process groups of currently running commands, so they can be signaled from main thread
+#[derive(Clone, Default)]
+pub struct RunningTasks {
+    tasks: Arc<Mutex<HashMap<String, RunningTask>>>
+}
+
+struct RunningTask {
+    pgid: u32,
+    aborted: bool,
+    output: LiveOutput
+}
+
+impl RunningTasks {
+
+    fn register(&self, task_id: &str, pgid: u32) -> LiveOutput {
+        let output = LiveOutput::default();
+        self.tasks.lock().unwrap().insert(task_id.to_owned(), RunningTask { pgid, aborted: false, output: output.clone() });
+        output
+    }
+
+    // returns true if task was signaled while running
+    fn unregister(&self, task_id: &str) -> bool {
+        self.tasks.lock().unwrap().remove(task_id).is_some_and(|task| task.aborted)
+    }
+
+    pub fn signal_all(&self, signal: libc::c_int) {
+        for task in self.tasks.lock().unwrap().values_mut() {
+            task.aborted = true;
+            kill_process_group(task.pgid, signal);
+        }
+    }
+
+    pub fn abort(&self, task_id: &str) {
+        if let Some(task) = self.tasks.lock().unwrap().get_mut(task_id) {
+            let signal = if task.aborted { libc::SIGKILL } else { libc::SIGTERM };
+            task.aborted = true;
+            kill_process_group(task.pgid, signal);
+        }
+    }
+
+    // last output of running task, None if task is not running
+    pub fn live_output(&self, task_id: &str) -> Option<String> {
+        self.tasks.lock().unwrap().get(task_id).map(|task| task.output.text())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.lock().unwrap().len()
+    }
+}
+
+// Tail of stdout and stderr of running task, in order it was read
+#[derive(Clone, Default)]
+struct LiveOutput {
+    buf: Arc<Mutex<VecDeque<u8>>>
+}
+
+impl LiveOutput {
+
+    fn push(&self, data: &[u8]) {
+        let mut buf = self.buf.lock().unwrap();
+        let data = &data[data.len().saturating_sub(LIVE_OUTPUT_SIZE)..];
+        let overflow = (buf.len() + data.len()).saturating_sub(LIVE_OUTPUT_SIZE);
+
+        buf.drain(..overflow);
+        buf.extend(data);
+    }
+
+    fn text(&self) -> String {
+        let buf = self.buf.lock().unwrap();
+        let (a, b) = buf.as_slices();
+        String::from_utf8_lossy(&[a, b].concat()).into_owned()
+    }
+}
+
+pub struct ExecCommandResult {
+    pub task_id: String,
+    pub exit_status: process::ExitStatus,
+    pub timed_out: bool,
+    pub aborted: bool,
+    pub command: String,
+    pub stdout: String,
+    pub stderr: String,
+    // files with full output, if spooling is enabled
+    pub stdout_path: Option<String>,
+    pub stderr_path: Option<String>,
+    pub elapsed_time_ms: u128,
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub worker_id: usize
+}
+
+impl ExecCommandResult {
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_status.code()
+    }
+
+    pub fn signal(&self) -> Option<i32> {
+        self.exit_status.signal()
+    }
+}
+
+impl std::fmt::Debug for ExecCommandResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ExecCommandResult {{ task_id: {}, exit_status: {:?}, timed_out: {}, aborted: {}, command: {} }}", self.task_id, self.exit_status, self.timed_out, self.aborted, self.command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn execute(command: &str, timeout: Option<Duration>) -> ExecCommandResult {
+        execute_command(command, "task", None, false, timeout, &OutputLimits::default(), &RunningTasks::default())
+    }
+
+    fn process_exists(pid: libc::pid_t) -> bool {
+        unsafe { libc::kill(pid, 0) == 0 }
+    }
+
+    fn kill(pid: libc::pid_t) {
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+        }
+    }
+
+    fn capped(max_size: usize, chunks: &[&str]) -> String {
+        let mut output = CappedOutput::new(max_size);
+
+        for chunk in chunks {
+            output.push(chunk.as_bytes());
+        }
+
+        output.into_string()
+    }
+
+    #[test]
+    fn output_is_kept_if_it_fits() {
+        assert_eq!(capped(0, &["a".repeat(100_000).as_str()]).len(), 100_000);
+        assert_eq!(capped(10, &["0123", "456789"]), "0123456789");
+        assert_eq!(capped(10, &[]), "");
+    }
+
+    #[test]
+    fn head_and_tail_of_output_are_kept() {
+        assert_eq!(capped(6, &["0123456789"]), "012\n[... 4 bytes truncated ...]\n789");
+        // the same when output arrives in small chunks
+        assert_eq!(capped(6, &["01", "2", "34", "5", "678", "9"]), "012\n[... 4 bytes truncated ...]\n789");
+        assert_eq!(capped(5, &["0123", "456789"]), "01\n[... 5 bytes truncated ...]\n789");
+    }
+
+    #[test]
+    fn truncated_multibyte_characters_are_replaced() {
+        assert_eq!(capped(4, &["ééé"]), "é\n[... 2 bytes truncated ...]\né");
+        assert_eq!(capped(3, &["ééé"]), "\u{FFFD}\n[... 3 bytes truncated ...]\né");
+    }
+
+    #[test]
+    fn file_names_are_escaped() {
+        assert_eq!(escape_file_name("task-1_a.b"), "task-1_a.b");
+        assert_eq!(escape_file_name("../etc/passwd"), "%2E.%2Fetc%2Fpasswd");
+        assert_eq!(escape_file_name(".hidden"), "%2Ehidden");
+        assert_eq!(escape_file_name("a b/é"), "a%20b%2F%C3%A9");
+        assert_eq!(escape_file_name(""), "");
+    }
+
+    #[test]
+    fn spool_files_are_named_by_task_and_stage() {
+        let limits = OutputLimits { max_size: 0, spool_dir: Some(PathBuf::from("logs")) };
+
+        assert_eq!(spool_path(&limits, "a/b", None, "out"), Some(PathBuf::from("logs/a%2Fb.out")));
+        assert_eq!(spool_path(&limits, "a", Some("fetch data"), "err"), Some(PathBuf::from("logs/a.fetch%20data.err")));
+        assert_eq!(spool_path(&OutputLimits::default(), "a", None, "out"), None);
+    }
+
+    #[test]
+    fn detached_processes_are_not_killed() {
+        let result = execute("nohup sleep 30 >/dev/null 2>&1 & echo $!", None);
+        let pid: libc::pid_t = result.stdout.trim().parse().unwrap();
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(process_exists(pid));
+        kill(pid);
+    }
+
+    #[test]
+    fn processes_holding_output_are_killed() {
+        let now = Instant::now();
+        let result = execute("sleep 30 & echo done", None);
+
+        assert!(now.elapsed() < Duration::from_secs(10));
+        assert_eq!(result.stdout, "done\n");
+        assert!(!result.timed_out);
+        assert_eq!(result.exit_code(), Some(0));
+    }
+
+    #[test]
+    fn timeout_does_not_wait_for_processes_in_other_sessions() {
+        let now = Instant::now();
+        let result = execute("setsid sh -c 'echo $$; exec sleep 30' & sleep 30", Some(Duration::from_millis(500)));
+        let pid: libc::pid_t = result.stdout.trim().parse().unwrap();
+
+        assert!(now.elapsed() < Duration::from_secs(10));
+        assert!(result.timed_out);
+        assert_eq!(result.signal(), Some(libc::SIGKILL));
+        kill(pid);
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use csv::{ReaderBuilder, StringRecord};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum_macros::{Display as StrumDisplay, EnumString};
+
+use crate::config::RunConfig;
+
+// header of column which contains task id in JSON input
+const TASK_ID_HEADER: &str = "task_id";
+
+#[derive(Clone, Copy, PartialEq, StrumDisplay, EnumString, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    #[strum(serialize = "csv")]
+    Csv,
+    #[strum(serialize = "tsv")]
+    Tsv,
+    // array of objects
+    #[strum(serialize = "json")]
+    Json,
+    // object per line
+    #[strum(serialize = "jsonl")]
+    Jsonl
+}
+
+impl InputFormat {
+
+    // by file extension, csv if extension is unknown
+    pub fn detect(path: &Path) -> InputFormat {
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+            Some("tsv") | Some("tab") => InputFormat::Tsv,
+            Some("json") => InputFormat::Json,
+            Some("jsonl") | Some("ndjson") => InputFormat::Jsonl,
+            _ => InputFormat::Csv
+        }
+    }
+}
+
+// Returns header row (if file has it) and task rows, task id is in the first column.
+// JSON objects are flattened: each field (nested ones too) becomes column with JSON pointer as header, e.g. /user/id
+pub fn load_tasks(path: &Path, config: &RunConfig) -> anyhow::Result<(Option<StringRecord>, Vec<StringRecord>)> {
+    let format = config.format.unwrap_or_else(|| InputFormat::detect(path));
+
+    match format {
+        InputFormat::Csv => load_csv(path, config.delimeter.as_bytes()[0], true, config.has_header),
+        // TSV has no quoting, quotes are part of values
+        InputFormat::Tsv => load_csv(path, b'\t', false, config.has_header),
+        InputFormat::Json => {
+            let content = fs::read_to_string(path).with_context(|| format!("Can not read tasks file {}", path.display()))?;
+            let tasks: Vec<Value> = serde_json::from_str(&content).context("Tasks file must contain JSON array")?;
+
+            flatten_tasks(tasks, &config.id_field)
+        },
+        InputFormat::Jsonl => {
+            let content = fs::read_to_string(path).with_context(|| format!("Can not read tasks file {}", path.display()))?;
+            let mut tasks = vec![];
+
+            for (idx, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+                tasks.push(serde_json::from_str(line).with_context(|| format!("Invalid JSON on line {}", idx + 1))?);
+            }
+
+            flatten_tasks(tasks, &config.id_field)
+        }
+    }
+}
+
+fn load_csv(path: &Path, delimeter: u8, quoting: bool, has_header: bool) -> anyhow::Result<(Option<StringRecord>, Vec<StringRecord>)> {
+    let mut reader = ReaderBuilder::default().delimiter(delimeter).quoting(quoting).has_headers(has_header).from_path(path)?;
+    let headers = if has_header { Some(reader.headers()?.clone()) } else { None };
+    let iter = reader.into_records();
+
+    let mut result: Vec<StringRecord> = vec![];
+
+    for record in iter {
+        let data = record?;
+        result.push(data);
+    }
+
+    Ok((headers, result))
+}
+
+// columns are fields of all tasks in order of appearance, missing fields are empty
+fn flatten_tasks(tasks: Vec<Value>, id_field: &str) -> anyhow::Result<(Option<StringRecord>, Vec<StringRecord>)> {
+    let mut headers = vec![TASK_ID_HEADER.to_owned()];
+    let mut columns: HashMap<String, usize> = HashMap::new();
+    let mut rows = vec![];
+
+    for (idx, task) in tasks.iter().enumerate() {
+        if !task.is_object() {
+            return Err(anyhow::anyhow!("Task {} is not a JSON object", idx + 1));
+        }
+
+        let task_id = match task.pointer(id_field) {
+            Some(Value::String(id)) => id.clone(),
+            Some(Value::Number(id)) => id.to_string(),
+            _ => return Err(anyhow::anyhow!("Task {} has no {} field with string or number id", idx + 1, id_field))
+        };
+
+        let mut fields = vec![];
+        flatten_value(task, String::new(), &mut fields);
+
+        let mut row = vec![String::new(); headers.len()];
+        row[0] = task_id;
+
+        for (pointer, value) in fields {
+            let column = *columns.entry(pointer.clone()).or_insert_with(|| {
+                headers.push(pointer);
+                headers.len() - 1
+            });
+
+            if column >= row.len() {
+                row.resize(column + 1, String::new());
+            }
+
+            row[column] = value;
+        }
+
+        rows.push(row);
+    }
+
+    // rows of earlier tasks do not have columns which appeared later
+    let rows = rows.into_iter().map(|mut row| {
+        row.resize(headers.len(), String::new());
+        StringRecord::from(row)
+    }).collect();
+
+    Ok((Some(StringRecord::from(headers)), rows))
+}
+
+// nested objects and arrays are available as JSON text too
+fn flatten_value(value: &Value, pointer: String, fields: &mut Vec<(String, String)>) {
+    if !pointer.is_empty() {
+        let text = match value {
+            Value::Null => String::new(),
+            Value::String(text) => text.clone(),
+            other => other.to_string()
+        };
+
+        fields.push((pointer.clone(), text));
+    }
+
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                flatten_value(value, format!("{}/{}", pointer, key.replace('~', "~0").replace('/', "~1")), fields);
+            }
+        },
+        Value::Array(items) => {
+            for (idx, value) in items.iter().enumerate() {
+                flatten_value(value, format!("{}/{}", pointer, idx), fields);
+            }
+        },
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flatten(tasks: &str, id_field: &str) -> anyhow::Result<(Vec<String>, Vec<Vec<String>>)> {
+        let (headers, rows) = flatten_tasks(serde_json::from_str(tasks).unwrap(), id_field)?;
+        let headers = headers.unwrap().iter().map(str::to_owned).collect();
+        let rows = rows.iter().map(|row| row.iter().map(str::to_owned).collect()).collect();
+
+        Ok((headers, rows))
+    }
+
+    #[test]
+    fn detects_format_by_extension() {
+        assert!(InputFormat::detect(Path::new("tasks.csv")) == InputFormat::Csv);
+        assert!(InputFormat::detect(Path::new("tasks.TSV")) == InputFormat::Tsv);
+        assert!(InputFormat::detect(Path::new("tasks.tab")) == InputFormat::Tsv);
+        assert!(InputFormat::detect(Path::new("tasks.json")) == InputFormat::Json);
+        assert!(InputFormat::detect(Path::new("tasks.jsonl")) == InputFormat::Jsonl);
+        assert!(InputFormat::detect(Path::new("tasks.ndjson")) == InputFormat::Jsonl);
+        assert!(InputFormat::detect(Path::new("tasks.txt")) == InputFormat::Csv);
+        assert!(InputFormat::detect(Path::new("tasks")) == InputFormat::Csv);
+    }
+
+    #[test]
+    fn columns_are_in_order_of_first_appearance() {
+        let (headers, rows) = flatten(r#"[{"id": "a", "zeta": "Z", "alpha": "A"}, {"id": 2, "beta": "B", "zeta": "Y"}]"#, "/id").unwrap();
+
+        assert_eq!(headers, vec!["task_id", "/id", "/zeta", "/alpha", "/beta"]);
+        assert_eq!(rows, vec![vec!["a", "a", "Z", "A", ""], vec!["2", "2", "Y", "", "B"]]);
+    }
+
+    #[test]
+    fn nested_values_are_flattened() {
+        let (headers, rows) = flatten(r#"[{"user": {"id": "u1", "a/b": null}, "tags": ["x", 1], "ok": true}]"#, "/user/id").unwrap();
+
+        assert_eq!(headers, vec!["task_id", "/user", "/user/id", "/user/a~1b", "/tags", "/tags/0", "/tags/1", "/ok"]);
+        assert_eq!(rows[0], vec!["u1", r#"{"id":"u1","a/b":null}"#, "u1", "", r#"["x",1]"#, "x", "1", "true"]);
+    }
+
+    #[test]
+    fn tasks_without_id_are_rejected() {
+        let err = flatten(r#"[{"id": "a"}, {"name": "b"}]"#, "/id").unwrap_err();
+        assert_eq!(err.to_string(), "Task 2 has no /id field with string or number id");
+
+        let err = flatten(r#"[{"id": ["a"]}]"#, "/id").unwrap_err();
+        assert_eq!(err.to_string(), "Task 1 has no /id field with string or number id");
+
+        let err = flatten(r#"[{"id": "a"}]"#, "id").unwrap_err();
+        assert_eq!(err.to_string(), "Task 1 has no id field with string or number id");
+
+        let err = flatten(r#"["a"]"#, "/id").unwrap_err();
+        assert_eq!(err.to_string(), "Task 1 is not a JSON object");
+    }
+
+    #[test]
+    fn tsv_values_can_start_with_quote() {
+        let path = std::env::temp_dir().join(format!("workman-input-{}.tsv", std::process::id()));
+        fs::write(&path, "a\t\"quoted\" value\tb\"c\n").unwrap();
+
+        let result = load_tasks(&path, &RunConfig::default());
+        fs::remove_file(&path).unwrap();
+
+        let (headers, rows) = result.unwrap();
+        assert!(headers.is_none());
+        assert_eq!(rows[0].iter().collect::<Vec<&str>>(), vec!["a", "\"quoted\" value", "b\"c"]);
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Number of running tasks per key (pipeline stage, group), so concurrency limits can be respected by scheduler
+pub struct Slots<K> {
+    limits: HashMap<K, usize>,
+    // limit of keys without own limit
+    default_limit: Option<usize>,
+    running: HashMap<K, usize>
+}
+
+impl<K: Hash + Eq + Clone> Slots<K> {
+
+    pub fn new(limits: HashMap<K, usize>, default_limit: Option<usize>) -> Slots<K> {
+        Slots { limits, default_limit, running: HashMap::new() }
+    }
+
+    // keys which can not start more tasks
+    pub fn full(&self) -> Vec<K> {
+        self.running.iter()
+            .filter(|(key, running)| self.limits.get(*key).copied().or(self.default_limit).is_some_and(|limit| **running >= limit))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    pub fn acquire(&mut self, key: K) {
+        *self.running.entry(key).or_default() += 1;
+    }
+
+    pub fn release(&mut self, key: &K) {
+        if let Some(running) = self.running.get_mut(key) {
+            *running = running.saturating_sub(1);
+
+            if *running == 0 {
+                self.running.remove(key);
+            }
+        }
+    }
+}
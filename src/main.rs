@@ -1,23 +1,45 @@
+mod budget;
+mod config;
+mod deps;
+mod executor;
+mod input;
+mod limits;
+mod migrations;
+mod pipeline;
+mod reporter;
+mod retry;
+mod shell;
+mod shutdown;
 mod storage;
+mod success;
+mod template;
 mod terminal;
 
 use anyhow::Context;
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches};
+use config::RunConfig;
+use executor::{ExecCommandResult, OutputLimits, RunningTasks};
+use limits::Slots;
+use shutdown::{Shutdown, ShutdownSignal};
 use storage::{TaskStatus, ConnHandle};
-use terminal::{LayoutData, TerminalUi};
+use reporter::{Reporter, TaskEvent, UiMode};
+use template::CommandTemplate;
+use terminal::LayoutData;
 use std::cmp::{max, min};
-use std::io::Read;
+use std::collections::HashSet;
 use std::path::Path;
 use std::process::exit;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
 use std::time::{Duration, Instant};
-use std::{fs, sync::Arc};
+use std::fs;
 use threadpool::ThreadPool;
-use std::{process, thread, u128};
-use std::sync::{mpsc}; 
-use csv::{ReaderBuilder, StringRecord};
+use std::thread;
+use std::sync::{mpsc, Arc}; 
+use csv::StringRecord;
+use strum_macros::Display as StrumDisplay;
+use termion::event::Key;
+use termion::input::TermRead;
 
 fn main() -> anyhow::Result<()> {
     let matches = App::new("workman")
@@ -27,21 +49,51 @@ fn main() -> anyhow::Result<()> {
         .subcommand(App::new("process")
             .about("Start worker pool and process task")
             .arg(Arg::new("tasks").long("tasks").short('t').takes_value(true).required(true).about("Path to tasks list file"))
-            .arg(Arg::new("workers").long("workers").short('w').takes_value(true).required(true).default_value("4").about("Number of workers"))
             .arg(Arg::new("db").long("database").short('d').takes_value(true).required(true).default_value("tasks.db").about("Path to database file"))
-            .arg(Arg::new("exec").long("exec").short('e').takes_value(true).required(true).about("Command to execute"))
-            .arg(Arg::new("tries").long("tries").takes_value(true).required(false).default_value("0").about("How many times to retry command if it fails"))
-            .arg(Arg::new("delay").long("retry-delay").takes_value(true).required(false).default_value("1").about("Number of seconds task will be in rescheduled state before picked up again"))            
+            .arg(Arg::new("exec").long("exec").short('e').takes_value(true).required_unless_present("pipeline").about("Command to execute"))
+            .arg(Arg::new("pipeline").long("pipeline").takes_value(true).conflicts_with("exec").about("Path to JSON file with pipeline stages, each with its own command, tries, workers and timeout"))
+            .arg(Arg::new("format").long("format").takes_value(true).possible_values(&["csv", "tsv", "json", "jsonl"]).about("Format of tasks file. Detected from file extension by default, csv if extension is unknown"))
+            .arg(Arg::new("id-field").long("id-field").takes_value(true).about("JSON pointer of field used as task id in JSON input, e.g. /user/id. Default is /id"))
+            .arg(Arg::new("rerender").long("rerender").takes_value(false).about("Render commands of tasks left in database by previous runs using new --exec or --pipeline"))
             .arg(Arg::new("delimeter").long("delimeter").takes_value(true).required(false).default_value(",").about("CSV delimeter"))            
             .arg(Arg::new("has-header").long("has-header").takes_value(false).required(false).about("Set this flag if first row of CSV file contains headers"))            
+            .arg(Arg::new("timeout-column").long("timeout-column").takes_value(true).required(false).about("Index of CSV column which overrides timeout for this task"))
+            .arg(Arg::new("priority").long("priority").takes_value(true).required(false).default_value("0").about("Priority of imported tasks. Tasks with higher priority are processed first, tasks with the same priority in import order"))
+            .arg(Arg::new("priority-column").long("priority-column").takes_value(true).required(false).about("Index of CSV column which overrides priority for this task"))
+            .arg(Arg::new("group-column").long("group-column").takes_value(true).required(false).about("Index of CSV column with task group (e.g. target host) used by --group-limit"))
+            .arg(Arg::new("depends-column").long("depends-column").takes_value(true).required(false).about("Index of CSV column with ids of tasks (separated by ';') which must be completed before this task"))
+            .args(execution_args(true))
+            .args(ui_args())
+        ).subcommand(App::new("resume")
+            .about("Continue processing tasks from existing database using stored settings")
+            .arg(Arg::new("db").long("database").short('d').takes_value(true).required(true).default_value("tasks.db").about("Path to database file"))
+            .arg(Arg::new("exec").long("exec").short('e').takes_value(true).about("New command to execute, requires --rerender"))
+            .arg(Arg::new("pipeline").long("pipeline").takes_value(true).conflicts_with("exec").about("Path to JSON file with new pipeline stages, requires --rerender"))
+            .arg(Arg::new("rerender").long("rerender").takes_value(false).about("Render commands of tasks which are not completed yet using new --exec or --pipeline"))
+            .args(execution_args(false))
+            .args(ui_args())
+        ).subcommand(App::new("runs")
+            .about("Show history of process and resume runs in JSON format")
+            .arg(Arg::new("db").long("database").short('d').takes_value(true).required(true).default_value("tasks.db").about("Path to database file"))
+        ).subcommand(App::new("attempts")
+            .about("Show history of task execution attempts in JSON format")
+            .arg(Arg::new("db").long("database").short('d').takes_value(true).required(true).default_value("tasks.db").about("Path to database file"))
+            .arg(Arg::new("task").takes_value(true).required(true).index(1).about("Task id"))
         ).subcommand(App::new("stats")
             .about("Show stats in JSON format")
             .arg(Arg::new("db").long("database").short('d').takes_value(true).required(true).default_value("tasks.db").about("Path to database file"))
+        ).subcommand(App::new("set-priority")
+            .about("Update priority of tasks specified in tasks file")
+            .setting(AppSettings::AllowNegativeNumbers)
+            .arg(Arg::new("tasks").long("tasks").short('t').takes_value(true).required(true).about("Path to tasks list file"))
+            .arg(Arg::new("db").long("database").short('d').takes_value(true).required(true).default_value("tasks.db").about("Path to database file"))
+            .arg(Arg::new("priority").takes_value(true).required(true).index(1).about("New priority"))
         ).subcommand(App::new("set-status")
             .about("Update tasks status for tasks specified in tasks file")
             .arg(Arg::new("tasks").long("tasks").short('t').takes_value(true).required(true).about("Path to tasks list file"))
             .arg(Arg::new("db").long("database").short('d').takes_value(true).required(true).default_value("tasks.db").about("Path to database file"))
             .arg(Arg::new("status").takes_value(true).required(true).index(1).about("New status"))
+            .args(ui_args())
         )
         .get_matches();
 
@@ -50,163 +102,111 @@ fn main() -> anyhow::Result<()> {
     if let Some(matches) = matches.subcommand_matches("process") {
         // read cli arguments
         let db_path = matches.value_of("db").unwrap().to_owned();
-        let exec_command = matches.value_of("exec").unwrap().to_owned();
         let tasks_list_file = matches.value_of("tasks").unwrap();
-        let num_of_workers: usize = matches.value_of_t("workers").unwrap();
-        let retries: u32 = matches.value_of_t("tries").unwrap();
-        let retry_delay: u32 = matches.value_of_t("delay").unwrap();
-        let delimeter: &str = matches.value_of("delimeter").unwrap();
-        let delimeter = delimeter.as_bytes()[0];
-        let has_header = matches.is_present("has-header");
+
+        let mut config = RunConfig::default();
+        config.apply_matches(matches)?;
 
         // setup ui
-        let mut ui = terminal::TerminalUi::new()?;
-        let mut ld: LayoutData = Default::default();
-        ui.clear();
+        let mut ui = create_reporter(matches)?;
+        let mut ld = LayoutData {
+            log_message: String::from("Creating database..."),
+            ..Default::default()
+        };
 
-        ld.log_message = String::from("Creating database...");
         ui.draw(&ld);
 
         // setup database
         let connection = storage::create_database(&db_path).context("Can not create database")?;
 
-        // import tasks
-        let tasks = load_tasks_from_file(tasks_list_file, delimeter, has_header)?;
-
-        for (record_idx, row) in tasks.iter().enumerate() {
-            if row.len() == 0 {
-                return Err(anyhow::anyhow!("At least one column required in a row"));
-            }
-
-            let task = &row[0];
-            ld.log_message = format!("Importing task {}: {}...", record_idx + 1, task);
-            ui.draw(&ld);
-            storage::import_task(&connection, &row, &exec_command);
+        // database can contain tasks imported by previous runs with other command
+        if let Some(stored_config) = storage::get_run_config(&connection)? {
+            check_command_change(&connection, &stored_config, &config, matches.is_present("rerender"))?;
         }
 
-        storage::mark_scheduled_tasks_as_new(&connection)?;
+        let run_id = storage::start_run(&connection, "process", &config, &hostname())?;
 
-        // setup thread pool
-        ld.log_message = String::from("Starting thread pool...");
-        ui.draw(&ld);
+        let result = import_tasks(&connection, tasks_list_file, &config, ui.as_mut(), &mut ld).and_then(|_| {
+            if matches.is_present("rerender") {
+                rerender_tasks(&connection, &config)?;
+            }
 
-        let pool = ThreadPool::new(num_of_workers);
-        let (tx, rx) = mpsc::channel();
+            storage::save_run_config(&connection, &config)?;
+            storage::mark_scheduled_tasks_as_new(&connection)?;
+            storage::mark_processing_tasks_as_new(&connection)?;
 
-        ld.log_message = String::from("Scheduling tasks...");
-        ui.draw(&ld);
+            run_tasks(&connection, &config, run_id, ui.as_mut(), &mut ld)
+        });
+        let result = finish_run(&connection, run_id, result)?;
 
-        // schedule tasks
-        schedule_tasks(&connection, retries, &mut ld, &mut ui, &tx, &pool)?;
+        // restore terminal before exit
+        drop(ui);
+        exit(result.exit_code());
+    } else if let Some(matches) = matches.subcommand_matches("resume") {
+        let db_path = matches.value_of("db").unwrap().to_owned();
 
-        let q_was_pressed = Arc::new(AtomicBool::new(false));
+        if !Path::new(&db_path).exists() {
+            return Err(anyhow::anyhow!("Database file {} does not exist", db_path));
+        }
 
-        // start thread to handle user input
-        {
-            let q_was_pressed = Arc::clone(&q_was_pressed);
+        let connection = storage::create_database(&db_path).context("Can not open database")?;
 
-            thread::spawn(move || {
-                // check Q was presset
-                let mut buf = vec![0; 1];
-                
-                if let Ok(_) = std::io::stdin().read(&mut buf) {
-                    // q pressed
-                    if buf[0] == 113 {
-                        q_was_pressed.store(true, Ordering::SeqCst);
-                    }
-                }
-            });
-        }
+        // settings from command line take precedence over stored ones
+        let stored_config = storage::get_run_config(&connection)?.unwrap_or_default();
+        let mut config = stored_config.clone();
+        config.apply_matches(matches)?;
 
-        // start main loop
-        {
-            let mut processed_tasks_count = 0;
-            let mut total_elapsed_time: u128 = 0;
-            let mut last_ui_refresh_time = Instant::now();
+        // pending tasks are rendered with new command only if asked explicitly
+        check_command_change(&connection, &stored_config, &config, matches.is_present("rerender"))?;
 
-            loop {
-                if q_was_pressed.load(Ordering::SeqCst) {
-                    ld.log_message = String::from("Q was pressed. Exiting...");
-                    ui.draw(&ld);
+        let rendered = if matches.is_present("rerender") {
+            Some(rerender_tasks(&connection, &config)?)
+        } else {
+            None
+        };
 
-                    storage::mark_pending_tasks_as_aborted(&connection)?;
+        storage::save_run_config(&connection, &config)?;
+        let run_id = storage::start_run(&connection, "resume", &config, &hostname())?;
 
-                    exit(3);
-                }
+        // setup ui
+        let mut ui = create_reporter(matches)?;
+        let mut ld: LayoutData = Default::default();
 
-                ld.log_message = String::from("Waiting for all jobs to complete... Press 'q' to quit");
+        // tasks left in processing or scheduled state by crashed or interrupted run
+        let recovered = storage::recover_interrupted_tasks(&connection)?;
+        ld.log_message = match rendered {
+            Some(rendered) => format!("Recovered {} interrupted tasks, rendered commands of {} tasks...", recovered, rendered),
+            None => format!("Recovered {} interrupted tasks...", recovered)
+        };
+        ui.draw(&ld);
 
-                // process chanel messages
-                loop {
-                    match rx.try_recv() {
-                        Ok(message) => {
-                            match message {
-                                ChannelMessage::CommandResult(result) => {
-                                    processed_tasks_count += 1;
-                                    total_elapsed_time += result.elapsed_time_ms;
-
-                                    ld.min_elapsed_time = match ld.min_elapsed_time {
-                                        None => Some(result.elapsed_time_ms),
-                                        Some(val) => Some(min(val, result.elapsed_time_ms))
-                                    };
-
-                                    ld.max_elapsed_time = match ld.max_elapsed_time {
-                                        None => Some(result.elapsed_time_ms),
-                                        Some(val) => Some(max(val, result.elapsed_time_ms))
-                                    };
-
-                                    if !result.exit_status.success() && retries > 0 {
-                                        // handle reshedule logic
-                                        let reshedule_count = storage::get_task_reshedule_count(&connection, &result.task_id).unwrap();
-                                        
-                                        if reshedule_count < retries {
-                                            storage::reshedule_task(&connection,  &result.task_id, retry_delay)?;
-                                        } else {
-                                            storage::update_task_from_result(&connection, &result).unwrap();
-                                        }
-                                    } else {
-                                        storage::update_task_from_result(&connection, &result).unwrap();
-                                    }
+        let result = run_tasks(&connection, &config, run_id, ui.as_mut(), &mut ld);
+        let result = finish_run(&connection, run_id, result)?;
 
-                                    // redraw stats (and prevent to many redraws if tasks complete very fast)
-                                    if last_ui_refresh_time.elapsed().as_millis() > 500 {
-                                        ld.tasks_stats_struct = storage::get_stats_struct(&connection)?;
-                                        ld.processed_tasks_count = processed_tasks_count;
-                                        ld.total_elapsed_time = total_elapsed_time;
-                                        ui.draw(&ld);
-                                        last_ui_refresh_time = Instant::now();
-                                    }
-                                },
-                                ChannelMessage::SetTaskStatus{task_id, status} => {
-                                    storage::set_task_status(&connection, &task_id, &status).unwrap();
-                                }
-                            };   
-                        }
-            
-                        Err(mpsc::TryRecvError::Empty) => break,
-            
-                        Err(mpsc::TryRecvError::Disconnected) => {
-                            println!("Receive channel disconnected");
-                            exit(1);
-                        }
-                    }
-                }
+        // restore terminal before exit
+        drop(ui);
+        exit(result.exit_code());
+    } else if let Some(matches) = matches.subcommand_matches("runs") {
+        let db_path = matches.value_of("db").unwrap().to_owned();
+        let connection = storage::create_database(&db_path).context("Can not create database")?;
 
-                if storage::get_number_of_incomplete_tasks(&connection)? == 0 {
-                    ld.tasks_stats_struct = storage::get_stats_struct(&connection)?;
-                    ui.draw(&ld);
-                    break;
-                }
+        let runs = storage::get_runs(&connection)?;
+        let serialized = serde_json::to_string_pretty(&runs).unwrap();
+        print!("{}", serialized);
+        exit(0);
+    } else if let Some(matches) = matches.subcommand_matches("attempts") {
+        let db_path = matches.value_of("db").unwrap().to_owned();
+        let task_id = matches.value_of("task").unwrap();
+        let connection = storage::create_database(&db_path).context("Can not create database")?;
 
-                schedule_tasks(&connection, retries, &mut ld, &mut ui, &tx, &pool)?;
-                thread::sleep(Duration::from_millis(500));
-            }
+        if storage::get_task_status(&connection, task_id).is_none() {
+            return Err(anyhow::anyhow!("Task {} does not exist", task_id));
         }
 
-        ld.log_message = String::from("All jobs complete");
-        ui.draw(&ld);
-
-        pool.join();
+        let attempts = storage::get_task_attempts(&connection, task_id)?;
+        let serialized = serde_json::to_string_pretty(&attempts).unwrap();
+        print!("{}", serialized);
+        exit(0);
     } else if let Some(matches) = matches.subcommand_matches("stats") {
         let db_path = matches.value_of("db").unwrap().to_owned();
         let connection = storage::create_database(&db_path).context("Can not create database")?;
@@ -215,6 +215,21 @@ fn main() -> anyhow::Result<()> {
         let serialized = serde_json::to_string_pretty(&stats).unwrap();
         print!("{}", serialized);
         exit(0);
+    } else if let Some(matches) = matches.subcommand_matches("set-priority") {
+        let db_path = matches.value_of("db").unwrap().to_owned();
+        let priority: i32 = matches.value_of_t("priority")?;
+        let tasks_list_file = matches.value_of("tasks").unwrap();
+
+        let connection = storage::create_database(&db_path).context("Can not create database")?;
+
+        let tasks = fs::read_to_string(tasks_list_file).context("Can not read tasks file")?;
+        let mut updated = 0;
+
+        for task in tasks.lines().filter(|task| !task.is_empty()) {
+            updated += storage::set_task_priority(&connection, task, priority)?;
+        }
+
+        println!("Updated priority of {} tasks", updated);
     } else if let Some(matches) = matches.subcommand_matches("set-status") {
         let db_path = matches.value_of("db").unwrap().to_owned();
         let new_status = matches.value_of("status").unwrap().to_owned();
@@ -226,9 +241,8 @@ fn main() -> anyhow::Result<()> {
         let status = TaskStatus::from_str(&new_status).unwrap_or_else(|_| panic!("Wrong status: {}", new_status));
 
         // setup ui
-        let mut ui = terminal::TerminalUi::new()?;
+        let mut ui = create_reporter(matches)?;
         let mut ld: LayoutData = Default::default();
-        ui.clear();
 
         // load tasks list
         let tasks = fs::read_to_string(tasks_list_file).context("Can not read tasks file")?;
@@ -245,6 +259,7 @@ fn main() -> anyhow::Result<()> {
         ld.log_message = "All tasks updated".to_owned();
         ld.tasks_stats_struct = storage::get_stats_struct(&connection)?;
         ui.draw(&ld);
+        ui.finish(&ld);
     } else {
         print!("Please specify command to run");
     }
@@ -252,86 +267,658 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn import_tasks(connection: &ConnHandle, tasks_list_file: &str, config: &RunConfig, ui: &mut dyn Reporter, ld: &mut LayoutData) -> anyhow::Result<()> {
+    let (headers, tasks) = input::load_tasks(Path::new(tasks_list_file), config)?;
+
+    // check placeholders before anything is imported
+    let command_templates = compile_templates(config, headers.as_ref())?;
+
+    // file is imported completely or not at all, e.g. if it contains dependency cycle
+    let tx = storage::begin_transaction(connection)?;
+
+    // named placeholders of all tasks are resolved by single stored header row
+    let header_names = headers.as_ref().map(|headers| headers.iter().map(str::to_owned).collect::<Vec<String>>());
+
+    if header_names != storage::get_task_headers(connection)? {
+        let pending = storage::get_pending_tasks(connection)?.len();
+
+        if pending > 0 {
+            return Err(anyhow::anyhow!("Columns of tasks file differ from the ones {} incomplete tasks were imported with, complete them first or use another database", pending));
+        }
+
+        storage::save_task_headers(connection, header_names.as_deref())?;
+    }
+
+    for (record_idx, row) in tasks.iter().enumerate() {
+        if row.is_empty() {
+            return Err(anyhow::anyhow!("At least one column required in a row"));
+        }
+
+        let task = &row[0];
+        ld.log_message = format!("Importing task {}: {}...", record_idx + 1, task);
+        ui.draw(ld);
+
+        let task_timeout = match config.timeout_column.and_then(|idx| row.get(idx)).map(str::trim) {
+            None | Some("") => None,
+            Some(value) => Some(value.parse::<u32>().with_context(|| format!("Wrong timeout value for task {}: {}", task, value))?)
+        };
+
+        let task_priority = match config.priority_column.and_then(|idx| row.get(idx)).map(str::trim) {
+            None | Some("") => config.priority,
+            Some(value) => value.parse::<i32>().with_context(|| format!("Wrong priority value for task {}: {}", task, value))?
+        };
+
+        let task_group = config.group_column.and_then(|idx| row.get(idx)).map(str::trim).filter(|group| !group.is_empty());
+        let depends_on = config.depends_column.and_then(|idx| row.get(idx)).map(deps::parse_dependencies).unwrap_or_default();
+
+        let ctx = template::Context { record: Some(row), task_id: task, attempt: None, worker: None };
+        let commands = command_templates.iter().map(|template| template.render(&ctx)).collect::<anyhow::Result<Vec<String>>>()?;
+
+        storage::import_task(connection, task, &row.iter().collect::<Vec<&str>>(), &commands, task_timeout, task_priority, task_group, &depends_on);
+    }
+
+    if let Some((task, parent)) = storage::find_unknown_dependency(connection)? {
+        return Err(anyhow::anyhow!("Task {} depends on unknown task {}", task, parent));
+    }
+
+    if let Some(cycle) = deps::find_cycle(&storage::get_task_dependencies(connection)?) {
+        return Err(anyhow::anyhow!("Tasks dependency cycle: {}", cycle.join(" -> ")));
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn compile_templates(config: &RunConfig, headers: Option<&StringRecord>) -> anyhow::Result<Vec<CommandTemplate>> {
+    config.command_templates().into_iter()
+        .map(|template| CommandTemplate::compile(template, headers))
+        .collect()
+}
+
+fn stored_headers(connection: &ConnHandle) -> anyhow::Result<Option<StringRecord>> {
+    Ok(storage::get_task_headers(connection)?.map(StringRecord::from))
+}
+
+// Commands are rendered when tasks are dispatched, so changed command would silently apply to tasks which are not completed yet
+fn check_command_change(connection: &ConnHandle, stored_config: &RunConfig, config: &RunConfig, rerender: bool) -> anyhow::Result<()> {
+    if rerender || config.command_templates() == stored_config.command_templates() || storage::get_pending_tasks(connection)?.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow::anyhow!("Command differs from the one tasks were imported with, use --rerender to apply it to tasks which are not completed yet"))
+}
+
+// Render commands of tasks which are not completed yet using current exec template (or pipeline)
+fn rerender_tasks(connection: &ConnHandle, config: &RunConfig) -> anyhow::Result<usize> {
+    let command_templates = compile_templates(config, stored_headers(connection)?.as_ref())?;
+    let tasks = storage::get_pending_tasks(connection)?;
+
+    for (task_id, stage) in &tasks {
+        let args = storage::get_task_args(connection, task_id)?
+            .ok_or_else(|| anyhow::anyhow!("Task {} was imported by older version of workman, its command can not be rendered again", task_id))?;
+
+        if *stage >= command_templates.len() {
+            return Err(anyhow::anyhow!("Task {} is at stage {}, but pipeline has {} stages", task_id, stage + 1, command_templates.len()));
+        }
+
+        let record = StringRecord::from(args);
+        let ctx = template::Context { record: Some(&record), task_id, attempt: None, worker: None };
+        let commands = command_templates.iter().map(|template| template.render(&ctx)).collect::<anyhow::Result<Vec<String>>>()?;
+
+        storage::update_task_commands(connection, task_id, *stage, &commands)?;
+    }
+
+    Ok(tasks.len())
+}
+
+fn run_tasks(connection: &ConnHandle, config: &RunConfig, run_id: i64, ui: &mut dyn Reporter, ld: &mut LayoutData) -> anyhow::Result<RunResult> {
+    ld.workers = config.workers;
+    let retry_policy = config.retry_policy();
+    let retry_rules = config.retry_rules()?;
+    let success_criteria = config.success_criteria()?;
+    let mut error_budget = config.error_budget();
+    let mut abort_reason: Option<String> = None;
+    let output_limits = config.output_limits();
+    // commands are rendered when task is dispatched, so attempt and worker are known
+    let command_templates = Arc::new(compile_templates(config, stored_headers(connection)?.as_ref())?);
+
+    if let Some(dir) = &output_limits.spool_dir {
+        fs::create_dir_all(dir).with_context(|| format!("Can not create output directory {}", dir.display()))?;
+    }
+
+    // setup thread pool
+    ld.log_message = String::from("Starting thread pool...");
+    ui.draw(ld);
+
+    let mut pool = ThreadPool::new(ld.workers);
+    let (tx, rx) = mpsc::channel();
+    let running_tasks = RunningTasks::default();
+    let mut in_flight: usize = 0;
+    let mut stage_slots = pipeline::stage_slots(&config.stages);
+    let mut group_slots = config.group_slots();
+    ld.stage_names = config.stages.iter().map(|stage| stage.name.clone()).collect();
+
+    // tasks which depend on tasks failed in previous runs, parents could be set to new since then
+    update_skipped_tasks(connection, ui, true)?;
+
+    ld.log_message = String::from("Scheduling tasks...");
+    ui.draw(ld);
+
+    // schedule tasks
+    in_flight += schedule_tasks(connection, config, &output_limits, &command_templates, &mut stage_slots, &mut group_slots, ld.workers, run_id, ld, ui, &tx, &pool, &running_tasks)?;
+
+    let shutdown_signal = ShutdownSignal::default();
+    shutdown_signal.register_os_signals()?;
+    let mut shutdown = Shutdown::new(config.shutdown_mode, Duration::from_secs(config.grace_period));
+
+    // start thread to handle user input
+    if ui.is_interactive() {
+        let tx = tx.clone();
+
+        thread::spawn(move || {
+            for key in std::io::stdin().keys().flatten() {
+                if tx.send(ChannelMessage::Key(key)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let mut processed_tasks_count = 0;
+    let mut total_elapsed_time: u128 = 0;
+
+    // start main loop
+    {
+        let mut last_ui_refresh_time = Instant::now();
+        let mut tick = Duration::from_millis(500);
+
+        loop {
+            if shutdown_signal.take_request() {
+                shutdown.request(&running_tasks);
+            }
+
+            if shutdown.in_progress() {
+                shutdown.tick(&running_tasks);
+                tick = Duration::from_millis(100);
+                ld.log_message = shutdown.status_message(running_tasks.len());
+
+                if let Some(reason) = &abort_reason {
+                    ld.log_message = format!("{}. {}", reason, ld.log_message);
+                }
+            } else if ui.is_interactive() {
+                ld.log_message = String::from("Waiting for all jobs to complete... Press 'q' to quit");
+            } else {
+                ld.log_message = String::from("Waiting for all jobs to complete...");
+            }
+
+            // process chanel messages, wait for the first one to arrive not longer than tick
+            let mut next_message = rx.recv_timeout(tick);
+            let mut redraw_now = false;
+
+            loop {
+                match next_message {
+                    Ok(message) => {
+                        match message {
+                            ChannelMessage::CommandResult(result) => {
+                                in_flight -= 1;
+                                processed_tasks_count += 1;
+                                total_elapsed_time += result.elapsed_time_ms;
+
+                                ld.min_elapsed_time = match ld.min_elapsed_time {
+                                    None => Some(result.elapsed_time_ms),
+                                    Some(val) => Some(min(val, result.elapsed_time_ms))
+                                };
+
+                                ld.max_elapsed_time = match ld.max_elapsed_time {
+                                    None => Some(result.elapsed_time_ms),
+                                    Some(val) => Some(max(val, result.elapsed_time_ms))
+                                };
+
+                                let stage = storage::get_task_stage(connection, &result.task_id).unwrap_or(0);
+                                stage_slots.release(&stage);
+
+                                if let Some(group) = storage::get_task_group(connection, &result.task_id) {
+                                    group_slots.release(&group);
+                                }
+
+                                let success = success_criteria.is_success(&result);
+                                let status = storage::status_from_result(&result, success);
+                                storage::insert_attempt(connection, run_id, stage, &status, &result)?;
+                                let reshedule_count = storage::get_task_reshedule_count(connection, &result.task_id).unwrap();
+
+                                if !success && !result.aborted && reshedule_count < config.stage_tries(stage) && retry_rules.should_retry(&result) {
+                                    // handle reshedule logic
+                                    let delay = retry_policy.delay_for(reshedule_count + 1);
+                                    storage::reshedule_task(connection,  &result.task_id, delay)?;
+                                    ui.task_event(TaskEvent::Rescheduled { task_id: &result.task_id, attempt: reshedule_count + 1, delay: delay.as_millis() as f64 / 1000.0 });
+                                } else if success && storage::advance_task_stage(connection, &result.task_id)? {
+                                    // output of finished stage is kept until next stage finishes
+                                    storage::update_task_output(connection, &result)?;
+                                    ui.task_event(TaskEvent::StageCompleted { task_id: &result.task_id, stage: config.stages[stage].name.as_str() });
+                                } else {
+                                    storage::update_task_from_result(connection, &result, &status).unwrap();
+                                    ui.task_event(TaskEvent::Finished {
+                                        task_id: &result.task_id,
+                                        status: status.to_string(),
+                                        exit_code: result.exit_code(),
+                                        signal: result.signal(),
+                                        elapsed_ms: result.elapsed_time_ms
+                                    });
+
+                                    if status != TaskStatus::Completed {
+                                        update_skipped_tasks(connection, ui, false)?;
+                                    }
+
+                                    // tasks aborted by user or shutdown are not counted as errors
+                                    if !result.aborted && abort_reason.is_none() {
+                                        if let Some(reason) = error_budget.record(&result.task_id, !success) {
+                                            abort_reason = Some(reason);
+                                            shutdown.request(&running_tasks);
+                                        }
+                                    }
+                                }
+                            },
+                            ChannelMessage::TaskStarted{task_id} => {
+                                storage::mark_task_processing(connection, &task_id).unwrap();
+                                ui.task_event(TaskEvent::Started { task_id: &task_id });
+                            },
+                            ChannelMessage::Key(key) => {
+                                handle_key(connection, key, ld, ui, &running_tasks, &shutdown_signal)?;
+                                redraw_now = true;
+
+                                // excess threads exit when they complete current task
+                                if pool.max_count() != ld.workers {
+                                    pool.set_num_threads(ld.workers);
+                                }
+                            }
+                        };   
+                    }
+        
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+        
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        println!("Receive channel disconnected");
+                        exit(1);
+                    }
+                }
+
+                next_message = rx.try_recv().map_err(|err| match err {
+                    mpsc::TryRecvError::Empty => mpsc::RecvTimeoutError::Timeout,
+                    mpsc::TryRecvError::Disconnected => mpsc::RecvTimeoutError::Disconnected
+                });
+            }
+
+            // redraw stats (and prevent to many redraws if tasks complete very fast)
+            if redraw_now || last_ui_refresh_time.elapsed().as_millis() > 500 {
+                ld.tasks_stats_struct = storage::get_stats_struct(connection)?;
+                ld.processed_tasks_count = processed_tasks_count;
+                ld.total_elapsed_time = total_elapsed_time;
+
+                if ui.is_interactive() {
+                    refresh_task_list(connection, ld)?;
+                    refresh_follow_output(ld, &running_tasks);
+                }
+
+                ui.draw(ld);
+                last_ui_refresh_time = Instant::now();
+            }
+
+            if shutdown.in_progress() {
+                if in_flight == 0 {
+                    break;
+                }
+
+                continue;
+            }
+
+            let incomplete = storage::get_number_of_incomplete_tasks(connection)?;
+
+            if incomplete == 0 {
+                ld.tasks_stats_struct = storage::get_stats_struct(connection)?;
+                ui.draw(ld);
+                break;
+            }
+
+            if !ld.paused {
+                let limit = ld.workers.saturating_sub(in_flight);
+                in_flight += schedule_tasks(connection, config, &output_limits, &command_templates, &mut stage_slots, &mut group_slots, limit, run_id, ld, ui, &tx, &pool, &running_tasks)?;
+
+                // nothing is running or waiting for retry and nothing can be started, e.g. database has dependency cycle
+                if in_flight == 0 && ld.workers > 0 && storage::get_number_of_waiting_tasks(connection, config.max_tries())? == 0 {
+                    let statuses = storage::get_incomplete_tasks_by_status(connection)?.iter()
+                        .map(|(status, count)| format!("{} {}", count, status))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+
+                    return Err(anyhow::anyhow!("{} incomplete tasks can not be started ({}), check dependencies of new tasks, --tries of rescheduled ones and other runs using the database", incomplete, statuses));
+                }
+            }
+        }
+    }
+
+    pool.join();
+
+    if shutdown.in_progress() {
+        // persist final state of tasks which were not started
+        storage::mark_scheduled_tasks_as_new(connection)?;
+        storage::mark_pending_tasks_as_aborted(connection)?;
+
+        ld.log_message = match &abort_reason {
+            Some(reason) => format!("{}. Run stopped", reason),
+            None => String::from("Shutdown complete")
+        };
+        ld.tasks_stats_struct = storage::get_stats_struct(connection)?;
+        ui.draw(ld);
+        ui.finish(ld);
+
+        return Ok(match abort_reason {
+            Some(reason) => RunResult::Failed(reason),
+            None => RunResult::Interrupted
+        });
+    }
+
+    ld.log_message = String::from("All jobs complete");
+    ld.tasks_stats_struct = storage::get_stats_struct(connection)?;
+    ld.processed_tasks_count = processed_tasks_count;
+    ld.total_elapsed_time = total_elapsed_time;
+    ui.draw(ld);
+    ui.finish(ld);
+
+    Ok(RunResult::Completed)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn schedule_tasks(
     connection: &ConnHandle, 
-    retries:u32, 
+    config: &RunConfig,
+    output_limits: &OutputLimits,
+    command_templates: &Arc<Vec<CommandTemplate>>,
+    stage_slots: &mut Slots<usize>,
+    group_slots: &mut Slots<String>,
+    limit: usize,
+    run_id: i64,
     ld: &mut LayoutData, 
-    ui: &mut TerminalUi, 
+    ui: &mut dyn Reporter, 
     tx: &Sender<ChannelMessage>,
-    pool: &ThreadPool
-) -> anyhow::Result<()> 
+    pool: &ThreadPool,
+    running_tasks: &RunningTasks
+) -> anyhow::Result<usize> 
 {
-    while let Some(task_id) = storage::get_next_task(connection, retries) {
+    let mut scheduled = 0;
+
+    // do not queue more tasks than there are free workers, so queue can be stopped at any moment
+    while scheduled < limit {
+        // tasks of other stages and groups fill workers when some of them reach their limits
+        let task = match storage::get_next_task(connection, config.max_tries(), &stage_slots.full(), &group_slots.full()) {
+            Some(task) => task,
+            None => break
+        };
+
+        let task_id = task.task_id;
+        let stage = task.stage;
+        stage_slots.acquire(stage);
+
+        if let Some(group) = task.group {
+            group_slots.acquire(group);
+        }
+
         ld.log_message = format!("Scheduling task {}...", task_id);
-        ui.draw(&ld);
+        ui.draw(ld);
 
         let tx = tx.clone();
-
-        storage::set_task_status(&connection, &task_id, &storage::TaskStatus::Scheduled)?;
-        let command_to_execute = storage::get_task_command(&connection, &task_id).expect("Can not get task command to execute");
+        let running_tasks = running_tasks.clone();
+        let output_limits = output_limits.clone();
+        let command_templates = command_templates.clone();
+        let argv = config.argv;
+        let stage_name = config.stages.get(stage).map(|stage| stage.name.clone());
+
+        storage::mark_task_scheduled(connection, &task_id, run_id)?;
+        ui.task_event(TaskEvent::Scheduled { task_id: &task_id });
+        // tasks imported by older versions have only rendered command
+        let task_args = storage::get_task_args(connection, &task_id)?;
+        let stored_command = storage::get_task_command(connection, &task_id).expect("Can not get task command to execute");
+        // attempt of current stage, the same as shown in tasks table and rescheduled event
+        let attempt = storage::get_task_reshedule_count(connection, &task_id).unwrap_or(0) + 1;
+
+        // task can override global (or stage) timeout, 0 means no timeout
+        let timeout = storage::get_task_timeout(connection, &task_id).unwrap_or_else(|| config.stage_timeout(stage));
+        let timeout = if timeout > 0 { Some(Duration::from_secs(timeout as u64)) } else { None };
 
         pool.execute( move || {
-            let message = ChannelMessage::SetTaskStatus {task_id: task_id.clone(), status: TaskStatus::Processing};
+            let message = ChannelMessage::TaskStarted {task_id: task_id.clone()};
             tx.send(message).unwrap();
 
-            let exec_result = execute_command(&command_to_execute, &task_id);
+            let exec_result = match task_args {
+                Some(args) => {
+                    let record = StringRecord::from(args);
+                    let ctx = template::Context { record: Some(&record), task_id: &task_id, attempt: Some(attempt), worker: Some(executor::worker_id()) };
+
+                    let command = command_templates.get(stage)
+                        .ok_or_else(|| anyhow::anyhow!("Task {} is at stage {}, but pipeline has {} stages", task_id, stage + 1, command_templates.len()))
+                        .and_then(|template| template.render(&ctx));
+
+                    match command {
+                        Ok(command) => executor::execute_command(&command, &task_id, stage_name.as_deref(), argv, timeout, &output_limits, &running_tasks),
+                        Err(err) => executor::spawn_error_result(&stored_command, &task_id, &err.to_string())
+                    }
+                },
+                None => executor::execute_command(&stored_command, &task_id, stage_name.as_deref(), argv, timeout, &output_limits, &running_tasks)
+            };
+
             let message = ChannelMessage::CommandResult(exec_result);
 
             tx.send(message).unwrap();
         });
+
+        scheduled += 1;
+    }
+
+    Ok(scheduled)
+}
+
+// Skips tasks blocked by failed parents, with unskip tasks skipped earlier are checked again.
+// Event is reported only for tasks which status changed
+fn update_skipped_tasks(connection: &ConnHandle, ui: &mut dyn Reporter, unskip: bool) -> anyhow::Result<()> {
+    let unskipped = if unskip { storage::unskip_tasks(connection)? } else { vec![] };
+    let skipped = storage::skip_blocked_tasks(connection)?;
+
+    let unskipped_ids: HashSet<&String> = unskipped.iter().collect();
+    let skipped_ids: HashSet<&String> = skipped.iter().collect();
+
+    for task_id in unskipped.iter().filter(|task_id| !skipped_ids.contains(task_id)) {
+        ui.task_event(TaskEvent::Requeued { task_id });
+    }
+
+    for task_id in skipped.iter().filter(|task_id| !unskipped_ids.contains(task_id)) {
+        ui.task_event(TaskEvent::Skipped { task_id });
+    }
+
+    Ok(())
+}
+
+fn handle_key(connection: &ConnHandle, key: Key, ld: &mut LayoutData, ui: &mut dyn Reporter, running_tasks: &RunningTasks, shutdown_signal: &ShutdownSignal) -> anyhow::Result<()> {
+    // terminal is in raw mode, so Ctrl+C does not send SIGINT
+    match key {
+        Key::Char('q') | Key::Ctrl('c') => shutdown_signal.request(),
+        Key::Esc => {
+            ld.task_details = None;
+            ld.follow_task = None;
+        },
+        Key::Up if ld.task_details.is_some() => ld.scroll_details(-1),
+        Key::Down if ld.task_details.is_some() => ld.scroll_details(1),
+        Key::PageUp if ld.task_details.is_some() => ld.scroll_details(-10),
+        Key::PageDown if ld.task_details.is_some() => ld.scroll_details(10),
+        Key::Up => ld.select_task(-1),
+        Key::Down => ld.select_task(1),
+        Key::PageUp => ld.select_task(-10),
+        Key::PageDown => ld.select_task(10),
+        Key::Char('\n') => {
+            if let Some(task_id) = ld.selected_task_id() {
+                ld.task_details = storage::get_task_details(connection, task_id)?;
+                ld.details_scroll = 0;
+                ld.follow_task = None;
+            }
+        },
+        Key::Char('o') => {
+            if let Some(task_id) = ld.selected_task_id() {
+                ld.follow_task = Some(task_id.to_owned());
+                ld.task_details = None;
+                refresh_follow_output(ld, running_tasks);
+            }
+        },
+        Key::Char('f') => {
+            ld.next_task_filter();
+            refresh_task_list(connection, ld)?;
+        },
+        Key::Char('p') => ld.paused = !ld.paused,
+        Key::Char('+') => ld.workers += 1,
+        Key::Char('-') => ld.workers = max(ld.workers.saturating_sub(1), 1),
+        Key::Char('r') => {
+            for task_id in storage::requeue_failed_tasks(connection)? {
+                ui.task_event(TaskEvent::Requeued { task_id: &task_id });
+            }
+
+            update_skipped_tasks(connection, ui, true)?;
+            refresh_task_list(connection, ld)?;
+        },
+        Key::Char('a') => {
+            // SIGTERM first, SIGKILL if task is aborted again
+            if let Some(task_id) = ld.selected_task_id() {
+                running_tasks.abort(task_id);
+            }
+        },
+        _ => {}
     }
 
     Ok(())
 }
 
-fn execute_command(command_str: &str, task_id: &str) -> ExecCommandResult {
-    let mut command = process::Command::new("sh");
-    command.arg("-c");
-    command.arg(command_str);
-    
-    let now = Instant::now();
-    let result = command.output().unwrap();
-    
-    ExecCommandResult {
-        task_id: task_id.to_owned(),
-        exit_status: result.status,
-        command: command_str.to_owned(),
-        stdout: String::from_utf8(result.stdout).unwrap(),
-        stderr: String::from_utf8(result.stderr).unwrap(),
-        elapsed_time_ms: now.elapsed().as_millis()
+fn refresh_task_list(connection: &ConnHandle, ld: &mut LayoutData) -> anyhow::Result<()> {
+    // keep selection on the same task, its position changes when other tasks finish
+    let selected_task_id = ld.selected_task_id().map(str::to_owned);
+    ld.task_list = storage::get_task_list(connection, ld.task_filter.as_ref(), 500)?;
+
+    if let Some(idx) = selected_task_id.and_then(|id| ld.task_list.iter().position(|task| task.task_id == id)) {
+        ld.selected_task = idx;
     }
+
+    ld.select_task(0);
+
+    // reload details, output is stored when task finishes
+    if let Some(details) = &ld.task_details {
+        let task_id = details.task_id.clone();
+        ld.task_details = storage::get_task_details(connection, &task_id)?;
+    }
+
+    Ok(())
+}
+
+fn refresh_follow_output(ld: &mut LayoutData, running_tasks: &RunningTasks) {
+    ld.follow_output = ld.follow_task.as_deref().and_then(|task_id| running_tasks.live_output(task_id));
 }
 
-fn load_tasks_from_file(path: impl AsRef<Path>, delimeter: u8, has_header: bool) -> anyhow::Result<Vec<StringRecord>> {
-    let reader = ReaderBuilder::default().delimiter(delimeter).has_headers(has_header).from_path(path)?;
-    let iter = reader.into_records();
+// record why run has finished, errors are recorded too
+fn finish_run(connection: &ConnHandle, run_id: i64, result: anyhow::Result<RunResult>) -> anyhow::Result<RunResult> {
+    let exit_reason = match &result {
+        Ok(RunResult::Failed(reason)) => format!("failed: {}", reason),
+        Ok(result) => result.to_string(),
+        Err(err) => format!("error: {}", err)
+    };
 
-    let mut result: Vec<StringRecord> = vec![];
+    storage::finish_run(connection, run_id, &exit_reason)?;
 
-    for record in iter {
-        let data = record?;
-        result.push(data);
+    result
+}
+
+fn hostname() -> String {
+    let mut buf = vec![0u8; 256];
+
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+
+    if result != 0 {
+        return String::new();
     }
 
-    Ok(result)
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+fn create_reporter(matches: &ArgMatches) -> anyhow::Result<Box<dyn Reporter>> {
+    let mode: UiMode = matches.value_of_t("ui")?;
+    let progress_interval: u64 = matches.value_of_t("progress-interval")?;
+
+    reporter::create_reporter(mode, Duration::from_secs(progress_interval))
 }
 
-pub struct ExecCommandResult {
-    task_id: String,
-    exit_status: process::ExitStatus,
-    command: String,
-    stdout: String,
-    stderr: String,
-    elapsed_time_ms: u128
+// arguments which control how progress is shown
+fn ui_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("ui").long("ui").takes_value(true).default_value("auto").possible_values(&["auto", "tui", "none", "plain", "json"])
+            .about("How to show progress: terminal UI, nothing, periodic one line summaries or JSON line per task state change. Auto uses terminal UI if stdout is a terminal and plain otherwise"),
+        Arg::new("progress-interval").long("progress-interval").takes_value(true).default_value("10").about("Number of seconds between summaries in plain mode")
+    ]
 }
 
-impl std::fmt::Debug for ExecCommandResult {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "ExecCommandResult {{ task_id: {}, exit_status: {:?}, command: {} }}", self.task_id, self.exit_status, self.command)
+// arguments which control tasks execution, shared by process and resume subcommands
+fn execution_args(with_defaults: bool) -> Vec<Arg<'static>> {
+    let args = vec![
+        (Arg::new("workers").long("workers").short('w').takes_value(true).about("Number of workers"), Some("4")),
+        (Arg::new("tries").long("tries").takes_value(true).about("How many times to retry command if it fails"), Some("0")),
+        (Arg::new("delay").long("retry-delay").takes_value(true).about("Number of seconds task will be in rescheduled state before picked up again. Base delay for linear and exponential retry policies"), Some("1")),
+        (Arg::new("retry-policy").long("retry-policy").takes_value(true).possible_values(&["fixed", "linear", "exponential"]).about("How retry delay grows with attempt number: fixed, linear (delay * attempt) or exponential (delay * 2 ^ (attempt - 1))"), Some("fixed")),
+        (Arg::new("retry-max-delay").long("retry-max-delay").takes_value(true).about("Maximum retry delay in seconds. 0 means no limit"), Some("0")),
+        (Arg::new("retry-jitter").long("retry-jitter").takes_value(true).about("Random part of retry delay, e.g. 0.2 means delay +-20%, so tasks which failed at the same time are not retried at the same time"), Some("0")),
+        (Arg::new("retry-on-exit").long("retry-on-exit").takes_value(true).about("Comma separated exit codes which are retried, e.g. 75,111. Other failures go straight to error"), None),
+        (Arg::new("no-retry-on-exit").long("no-retry-on-exit").takes_value(true).about("Comma separated exit codes which are never retried, e.g. 2"), None),
+        (Arg::new("retry-if-stderr-matches").long("retry-if-stderr-matches").takes_value(true).about("Retry failed task only if its stderr matches regular expression. Combined with --retry-on-exit, either of them allows retry"), None),
+        (Arg::new("success-exit-codes").long("success-exit-codes").takes_value(true).about("Comma separated exit codes which mean success"), Some("0")),
+        (Arg::new("success-if-stdout-matches").long("success-if-stdout-matches").takes_value(true).about("Task is successful only if its stdout matches regular expression"), None),
+        (Arg::new("fail-if-stderr-matches").long("fail-if-stderr-matches").takes_value(true).about("Task fails if its stderr matches regular expression, even if exit code means success"), None),
+        (Arg::new("success-json-field").long("success-json-field").takes_value(true).about("Task is successful only if stdout (or its last line) is JSON with given field value, e.g. result.status=ok"), None),
+        (Arg::new("fail-fast").long("fail-fast").takes_value(false).about("Stop run when the first task fails"), None),
+        (Arg::new("max-errors").long("max-errors").takes_value(true).about("Stop run when given number of tasks failed. 0 means no limit"), Some("0")),
+        (Arg::new("max-error-rate").long("max-error-rate").takes_value(true).about("Stop run when given share of last finished tasks failed, e.g. 20%"), None),
+        (Arg::new("error-rate-window").long("error-rate-window").takes_value(true).about("Number of last finished tasks error rate is calculated over"), Some("100")),
+        (Arg::new("max-output-size").long("max-output-size").takes_value(true).about("Maximum size of stdout and stderr stored in database per task, e.g. 512K or 10M. Beginning and end of bigger output is kept. 0 means no limit"), Some("1M")),
+        (Arg::new("output-dir").long("output-dir").alias("log-dir").takes_value(true).about("Directory where stdout and stderr of each task are written to as soon as they arrive (<task id>.out and <task id>.err)"), None),
+        (Arg::new("group-limit").long("group-limit").takes_value(true).multiple_occurrences(true).about("Maximum number of running tasks of a group: <group>=N for one group or N for every group. Can be repeated"), None),
+        (Arg::new("argv").long("argv").takes_value(false).about("Run command directly, without shell: command is split into words and placeholder values are passed as separate arguments"), None),
+        (Arg::new("timeout").long("timeout").takes_value(true).about("Number of seconds command is allowed to run before it is killed. 0 means no timeout"), Some("0")),
+        (Arg::new("shutdown-mode").long("shutdown-mode").takes_value(true).possible_values(&["drain", "terminate"]).about("What to do with running tasks on 'q', SIGINT, SIGTERM or SIGHUP: wait for them to complete or terminate them"), Some("terminate")),
+        (Arg::new("grace-period").long("grace-period").takes_value(true).about("Number of seconds to wait after SIGTERM before running tasks are killed"), Some("10"))
+    ];
+
+    args.into_iter().map(|(arg, default)| match default {
+        Some(default) if with_defaults => arg.default_value(default),
+        _ => arg
+    }).collect()
+}
+
+#[derive(StrumDisplay)]
+enum RunResult {
+    #[strum(serialize = "completed")]
+    Completed,
+    #[strum(serialize = "interrupted")]
+    Interrupted,
+    // stopped because error budget is exceeded
+    #[strum(serialize = "failed")]
+    Failed(String)
+}
+
+impl RunResult {
+
+    fn exit_code(&self) -> i32 {
+        match self {
+            RunResult::Completed => 0,
+            RunResult::Interrupted => 3,
+            RunResult::Failed(_) => 4
+        }
     }
 }
 
 enum ChannelMessage {
     CommandResult(ExecCommandResult),
-    SetTaskStatus { task_id: String, status: TaskStatus }
+    Key(Key),
+    TaskStarted { task_id: String }
 }
\ No newline at end of file
@@ -0,0 +1,440 @@
+use rusqlite::{Connection, OptionalExtension};
+
+// Each migration upgrades schema from previous version. Version is stored in PRAGMA user_version.
+// Never change existing migrations, add new one instead
+const MIGRATIONS: &[fn(&Connection) -> rusqlite::Result<()>] = &[
+    migrate_v1,
+    migrate_v2,
+    migrate_v3,
+    migrate_v4,
+    migrate_v5,
+    migrate_v6,
+    migrate_v7,
+    migrate_v8,
+    migrate_v9,
+    migrate_v10
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+pub fn migrate(connection: &mut Connection) -> anyhow::Result<()> {
+    let mut version = get_schema_version(connection)?;
+
+    if version > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "Database schema version {} is newer than supported version {}. Please upgrade workman",
+            version, SCHEMA_VERSION
+        ));
+    }
+
+    while version < SCHEMA_VERSION {
+        let tx = connection.transaction()?;
+        MIGRATIONS[version as usize](&tx)?;
+        version += 1;
+        tx.pragma_update(None, "user_version", &version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+fn get_schema_version(connection: &Connection) -> rusqlite::Result<u32> {
+    let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    if version > 0 {
+        return Ok(version);
+    }
+
+    // databases created before schema versioning have tasks table, but no version
+    let has_tasks: Option<String> = connection.query_row(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'tasks'",
+        [],
+        |row| row.get(0)
+    ).optional()?;
+
+    Ok(if has_tasks.is_some() { 1 } else { 0 })
+}
+
+fn add_column_if_missing(connection: &Connection, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    let mut stmt = connection.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?.collect::<rusqlite::Result<Vec<String>>>()?;
+
+    if !columns.iter().any(|c| c == column) {
+        connection.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+
+    Ok(())
+}
+
+// schema of workman 0.5.2
+fn migrate_v1(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS tasks (
+             task_id VARCHAR(255) primary key,
+             status VARCHAR(255) not null,
+             command TEXT,
+             elapsed_time VARCHAR(255),
+             reshedule_count INT not null,
+             ignore_till INT null,
+             stdout TEXT,
+             stderr TEXT
+         )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+// timeouts, run history and attempts history
+fn migrate_v2(connection: &Connection) -> rusqlite::Result<()> {
+    // databases created by unreleased versions can already have some of these columns
+    add_column_if_missing(connection, "tasks", "timeout", "INT null")?;
+    add_column_if_missing(connection, "tasks", "run_id", "INT null")?;
+
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS settings (
+             name VARCHAR(255) primary key,
+             value TEXT
+         )",
+        [],
+    )?;
+
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS runs (
+             id INTEGER primary key autoincrement,
+             command VARCHAR(255) not null,
+             config TEXT not null,
+             host VARCHAR(255),
+             started_at INT not null,
+             finished_at INT null,
+             exit_reason VARCHAR(255) null
+         )",
+        [],
+    )?;
+
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS attempts (
+             id INTEGER primary key autoincrement,
+             task_id VARCHAR(255) not null,
+             run_id INT null,
+             attempt INT not null,
+             worker_id INT,
+             started_at INT not null,
+             finished_at INT not null,
+             exit_code INT null,
+             signal INT null,
+             elapsed_ms INT not null,
+             stdout TEXT,
+             stderr TEXT
+         )",
+        [],
+    )?;
+
+    connection.execute("CREATE INDEX IF NOT EXISTS attempts_task_id ON attempts (task_id)", [])?;
+
+    Ok(())
+}
+
+// exit code, signal, timestamps and integer elapsed time. SQLite can not change column type,
+// so tasks table is rebuilt (rowid order is preserved)
+fn migrate_v3(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(
+        "CREATE TABLE tasks_v3 (
+             task_id VARCHAR(255) primary key,
+             status VARCHAR(255) not null,
+             command TEXT,
+             reshedule_count INT not null,
+             ignore_till INT null,
+             stdout TEXT,
+             stderr TEXT,
+             timeout INT null,
+             run_id INT null,
+             exit_code INT null,
+             signal INT null,
+             elapsed_ms INT null,
+             created_at INT null,
+             started_at INT null,
+             finished_at INT null
+         )",
+        [],
+    )?;
+
+    connection.execute(
+        "INSERT INTO tasks_v3 (task_id, status, command, reshedule_count, ignore_till, stdout, stderr, timeout, run_id, elapsed_ms)
+         SELECT task_id, status, command, reshedule_count, ignore_till, stdout, stderr, timeout, run_id, CAST(elapsed_time AS INTEGER)
+         FROM tasks ORDER BY rowid",
+        [],
+    )?;
+
+    connection.execute("DROP TABLE tasks", [])?;
+    connection.execute("ALTER TABLE tasks_v3 RENAME TO tasks", [])?;
+
+    Ok(())
+}
+
+// files with full output of tasks
+fn migrate_v4(connection: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(connection, "tasks", "stdout_path", "TEXT null")?;
+    add_column_if_missing(connection, "tasks", "stderr_path", "TEXT null")?;
+
+    Ok(())
+}
+
+// task priorities, tasks are picked by priority and then in import order
+fn migrate_v5(connection: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(connection, "tasks", "priority", "INT not null default 0")?;
+    connection.execute("CREATE INDEX IF NOT EXISTS tasks_status_priority ON tasks (status, priority)", [])?;
+
+    Ok(())
+}
+
+// dependencies between tasks, task is processed only when all tasks it depends on are completed
+fn migrate_v6(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS task_deps (
+             task_id VARCHAR(255) not null,
+             depends_on VARCHAR(255) not null,
+             primary key (task_id, depends_on)
+         )",
+        [],
+    )?;
+
+    connection.execute("CREATE INDEX IF NOT EXISTS task_deps_depends_on ON task_deps (depends_on)", [])?;
+
+    Ok(())
+}
+
+// pipeline stages, task runs command of current stage and keeps commands of all stages
+fn migrate_v7(connection: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(connection, "tasks", "stage", "INT not null default 0")?;
+    add_column_if_missing(connection, "tasks", "stage_commands", "TEXT null")?;
+
+    Ok(())
+}
+
+// task groups with concurrency limits, e.g. target host
+fn migrate_v8(connection: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(connection, "tasks", "task_group", "VARCHAR(255) null")?;
+
+    Ok(())
+}
+
+// raw values of tasks file row, command is rendered from them when task is dispatched
+fn migrate_v9(connection: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(connection, "tasks", "task_args", "TEXT null")?;
+
+    Ok(())
+}
+
+// stage and status of each attempt, attempts of pipeline tasks are numbered within stage. Stage of older attempts is unknown
+fn migrate_v10(connection: &Connection) -> rusqlite::Result<()> {
+    add_column_if_missing(connection, "attempts", "stage", "INT null")?;
+    add_column_if_missing(connection, "attempts", "status", "VARCHAR(255) null")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // databases produced by previous versions of workman
+    const FIXTURE_V1: &str = include_str!("../tests/fixtures/schema_v1.sql");
+    const FIXTURE_V2: &str = include_str!("../tests/fixtures/schema_v2.sql");
+    const FIXTURE_V3: &str = include_str!("../tests/fixtures/schema_v3.sql");
+    const FIXTURE_V4: &str = include_str!("../tests/fixtures/schema_v4.sql");
+    const FIXTURE_V5: &str = include_str!("../tests/fixtures/schema_v5.sql");
+    const FIXTURE_V6: &str = include_str!("../tests/fixtures/schema_v6.sql");
+    const FIXTURE_V7: &str = include_str!("../tests/fixtures/schema_v7.sql");
+    const FIXTURE_V8: &str = include_str!("../tests/fixtures/schema_v8.sql");
+    const FIXTURE_V9: &str = include_str!("../tests/fixtures/schema_v9.sql");
+
+    fn open_fixture(sql: &str) -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(sql).unwrap();
+        connection
+    }
+
+    fn columns(connection: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = connection.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        stmt.query_map([], |row| row.get(1)).unwrap().collect::<rusqlite::Result<Vec<String>>>().unwrap()
+    }
+
+    fn user_version(connection: &Connection) -> u32 {
+        connection.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn creates_latest_schema_in_empty_database() {
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+        assert!(columns(&connection, "tasks").contains(&"run_id".to_owned()));
+        assert!(!columns(&connection, "attempts").is_empty());
+    }
+
+    #[test]
+    fn upgrades_v1_database_and_keeps_tasks() {
+        let mut connection = open_fixture(FIXTURE_V1);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+        assert!(columns(&connection, "tasks").contains(&"timeout".to_owned()));
+
+        let stdout: String = connection.query_row("SELECT stdout FROM tasks WHERE task_id = '1'", [], |row| row.get(0)).unwrap();
+        assert_eq!(stdout, "1\n");
+
+        let count: u32 = connection.query_row("SELECT COUNT(task_id) FROM tasks", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn upgrades_v2_database_and_converts_elapsed_time() {
+        let mut connection = open_fixture(FIXTURE_V2);
+        assert_eq!(user_version(&connection), 2);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+        assert!(!columns(&connection, "tasks").contains(&"elapsed_time".to_owned()));
+
+        let (elapsed_ms, timeout): (i64, u32) = connection.query_row(
+            "SELECT elapsed_ms, timeout FROM tasks WHERE task_id = 'a'", [], |row| Ok((row.get(0)?, row.get(1)?))
+        ).unwrap();
+        assert_eq!(elapsed_ms, 2010);
+        assert_eq!(timeout, 5);
+
+        let attempts: u32 = connection.query_row("SELECT COUNT(id) FROM attempts", [], |row| row.get(0)).unwrap();
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn upgrades_v3_database_and_adds_output_paths() {
+        let mut connection = open_fixture(FIXTURE_V3);
+        assert_eq!(user_version(&connection), 3);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+        assert!(columns(&connection, "tasks").contains(&"stdout_path".to_owned()));
+
+        let (exit_code, stdout_path): (i32, Option<String>) = connection.query_row(
+            "SELECT exit_code, stdout_path FROM tasks WHERE task_id = 'a'", [], |row| Ok((row.get(0)?, row.get(1)?))
+        ).unwrap();
+        assert_eq!(exit_code, 0);
+        assert_eq!(stdout_path, None);
+    }
+
+    #[test]
+    fn upgrades_v4_database_with_default_priority() {
+        let mut connection = open_fixture(FIXTURE_V4);
+        assert_eq!(user_version(&connection), 4);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+
+        let priorities: Vec<i32> = connection.prepare("SELECT priority FROM tasks ORDER BY rowid").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<Vec<i32>>>().unwrap();
+        assert_eq!(priorities, vec![0, 0]);
+    }
+
+    #[test]
+    fn upgrades_v5_database_without_dependencies() {
+        let mut connection = open_fixture(FIXTURE_V5);
+        assert_eq!(user_version(&connection), 5);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+        assert_eq!(columns(&connection, "task_deps"), vec!["task_id".to_owned(), "depends_on".to_owned()]);
+
+        let deps: u32 = connection.query_row("SELECT COUNT(task_id) FROM task_deps", [], |row| row.get(0)).unwrap();
+        assert_eq!(deps, 0);
+    }
+
+    #[test]
+    fn upgrades_v6_database_to_single_stage_tasks() {
+        let mut connection = open_fixture(FIXTURE_V6);
+        assert_eq!(user_version(&connection), 6);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+
+        let (stage, stage_commands, command): (u32, Option<String>, String) = connection.query_row(
+            "SELECT stage, stage_commands, command FROM tasks WHERE task_id = 'b'", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        ).unwrap();
+        assert_eq!(stage, 0);
+        assert_eq!(stage_commands, None);
+        assert_eq!(command, "echo b");
+    }
+
+    #[test]
+    fn upgrades_v7_database_without_groups() {
+        let mut connection = open_fixture(FIXTURE_V7);
+        assert_eq!(user_version(&connection), 7);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+
+        let groups: u32 = connection.query_row("SELECT COUNT(task_id) FROM tasks WHERE task_group IS NOT NULL", [], |row| row.get(0)).unwrap();
+        assert_eq!(groups, 0);
+    }
+
+    #[test]
+    fn upgrades_v8_database_and_keeps_rendered_commands() {
+        let mut connection = open_fixture(FIXTURE_V8);
+        assert_eq!(user_version(&connection), 8);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+
+        let (command, task_args): (String, Option<String>) = connection.query_row(
+            "SELECT command, task_args FROM tasks WHERE task_id = 'b'", [], |row| Ok((row.get(0)?, row.get(1)?))
+        ).unwrap();
+        assert_eq!(command, "echo b");
+        assert_eq!(task_args, None);
+    }
+
+    #[test]
+    fn upgrades_v9_database_with_unknown_attempt_stages() {
+        let mut connection = open_fixture(FIXTURE_V9);
+        assert_eq!(user_version(&connection), 9);
+
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+
+        let (attempt, stage, status): (u32, Option<u32>, Option<String>) = connection.query_row(
+            "SELECT attempt, stage, status FROM attempts WHERE task_id = 'a'", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        ).unwrap();
+        assert_eq!(attempt, 1);
+        assert_eq!(stage, None);
+        assert_eq!(status, None);
+    }
+
+    #[test]
+    fn migrating_latest_database_is_noop() {
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+
+        assert_eq!(user_version(&connection), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn refuses_database_from_newer_version() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.pragma_update(None, "user_version", &(SCHEMA_VERSION + 1)).unwrap();
+
+        assert!(migrate(&mut connection).is_err());
+    }
+}
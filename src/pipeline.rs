@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::limits::Slots;
+
+// One step of pipeline. Settings which are not given are taken from command line
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Stage {
+    pub name: String,
+    pub exec: String,
+    pub tries: Option<u32>,
+    // maximum number of tasks running this stage at the same time
+    pub workers: Option<usize>,
+    pub timeout: Option<u32>
+}
+
+#[derive(Deserialize)]
+struct PipelineFile {
+    stages: Vec<Stage>
+}
+
+// Load stages from JSON file: {"stages": [{"name": "download", "exec": "curl -o {{0}}.zip {{1}}", "tries": 3, "workers": 2}, ...]}
+pub fn load_stages(path: impl AsRef<Path>) -> anyhow::Result<Vec<Stage>> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).with_context(|| format!("Can not read pipeline file {}", path.display()))?;
+    let pipeline: PipelineFile = serde_json::from_str(&content).with_context(|| format!("Can not parse pipeline file {}", path.display()))?;
+
+    if pipeline.stages.is_empty() {
+        return Err(anyhow::anyhow!("Pipeline must have at least one stage"));
+    }
+
+    for (idx, stage) in pipeline.stages.iter().enumerate() {
+        if stage.name.is_empty() || stage.exec.is_empty() {
+            return Err(anyhow::anyhow!("Stage {} must have name and exec", idx + 1));
+        }
+
+        if pipeline.stages[..idx].iter().any(|other| other.name == stage.name) {
+            return Err(anyhow::anyhow!("Duplicate stage name: {}", stage.name));
+        }
+
+        if stage.workers == Some(0) {
+            return Err(anyhow::anyhow!("Stage {} must have at least one worker", stage.name));
+        }
+    }
+
+    Ok(pipeline.stages)
+}
+
+// concurrency limits of stages which have them
+pub fn stage_slots(stages: &[Stage]) -> Slots<usize> {
+    let limits = stages.iter().enumerate().filter_map(|(idx, stage)| stage.workers.map(|workers| (idx, workers))).collect();
+    Slots::new(limits, None)
+}
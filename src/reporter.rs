@@ -0,0 +1,181 @@
+use std::io;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use strum_macros::{EnumString, Display as StrumDisplay};
+
+use crate::storage::TaskStatsResult;
+use crate::terminal::{LayoutData, TerminalUi};
+
+#[derive(Clone, Copy, PartialEq, StrumDisplay, EnumString)]
+pub enum UiMode {
+    // terminal UI if stdout is a terminal, plain text otherwise
+    #[strum(serialize = "auto")]
+    Auto,
+    #[strum(serialize = "tui")]
+    Tui,
+    #[strum(serialize = "none")]
+    None,
+    #[strum(serialize = "plain")]
+    Plain,
+    #[strum(serialize = "json")]
+    Json
+}
+
+// Shows progress to user. Terminal UI is used when stdout is a terminal, plain text or JSON lines otherwise
+pub trait Reporter {
+    fn draw(&mut self, data: &LayoutData);
+
+    fn task_event(&mut self, _event: TaskEvent) {}
+
+    fn finish(&mut self, _data: &LayoutData) {}
+
+    // true if reporter reads keys from stdin (terminal is in raw mode)
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+pub fn create_reporter(mode: UiMode, progress_interval: Duration) -> anyhow::Result<Box<dyn Reporter>> {
+    let mode = match mode {
+        UiMode::Auto if termion::is_tty(&io::stdout()) => UiMode::Tui,
+        UiMode::Auto => UiMode::Plain,
+        mode => mode
+    };
+
+    Ok(match mode {
+        UiMode::Tui => {
+            let mut ui = TerminalUi::new()?;
+            ui.clear();
+            Box::new(ui)
+        },
+        UiMode::Plain => Box::new(PlainReporter::new(progress_interval)),
+        UiMode::Json => Box::new(JsonReporter),
+        UiMode::None | UiMode::Auto => Box::new(NoneReporter)
+    })
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event")]
+pub enum TaskEvent<'a> {
+    #[serde(rename = "task_scheduled")]
+    Scheduled { task_id: &'a str },
+    #[serde(rename = "task_started")]
+    Started { task_id: &'a str },
+    #[serde(rename = "task_rescheduled")]
+    Rescheduled { task_id: &'a str, attempt: u32, delay: f64 },
+    // failed task is set to new again
+    #[serde(rename = "task_requeued")]
+    Requeued { task_id: &'a str },
+    // task depends on failed task
+    #[serde(rename = "task_skipped")]
+    Skipped { task_id: &'a str },
+    #[serde(rename = "task_stage_completed")]
+    StageCompleted { task_id: &'a str, stage: &'a str },
+    #[serde(rename = "task_finished")]
+    Finished { task_id: &'a str, status: String, exit_code: Option<i32>, signal: Option<i32>, elapsed_ms: u128 }
+}
+
+impl Reporter for TerminalUi {
+
+    fn draw(&mut self, data: &LayoutData) {
+        TerminalUi::draw(self, data);
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+pub struct NoneReporter;
+
+impl Reporter for NoneReporter {
+
+    fn draw(&mut self, _data: &LayoutData) {}
+}
+
+// Prints one line summary every progress interval
+pub struct PlainReporter {
+    started_at: Instant,
+    last_print_at: Option<Instant>,
+    interval: Duration
+}
+
+impl PlainReporter {
+
+    pub fn new(interval: Duration) -> PlainReporter {
+        PlainReporter {
+            started_at: Instant::now(),
+            last_print_at: None,
+            interval
+        }
+    }
+
+    fn print(&mut self, data: &LayoutData) {
+        let stats = &data.tasks_stats_struct;
+
+        println!(
+            "[{}s] total: {}, new: {}, scheduled: {}, processing: {}, rescheduled: {}, completed: {}, error: {}, timeout: {}, aborted: {}, skipped: {} | {}",
+            self.started_at.elapsed().as_secs(), stats.total, stats.new, stats.scheduled, stats.processing, stats.rescheduled,
+            stats.completed, stats.error, stats.timeout, stats.aborted, stats.skipped, data.log_message
+        );
+
+        self.last_print_at = Some(Instant::now());
+    }
+}
+
+impl Reporter for PlainReporter {
+
+    fn draw(&mut self, data: &LayoutData) {
+        if self.last_print_at.is_none_or(|t| t.elapsed() >= self.interval) {
+            self.print(data);
+        }
+    }
+
+    fn finish(&mut self, data: &LayoutData) {
+        self.print(data);
+    }
+}
+
+// Prints one JSON object per line for each task state change
+pub struct JsonReporter;
+
+#[derive(Serialize)]
+struct JsonLine<'a, T: Serialize> {
+    ts: u64,
+    #[serde(flatten)]
+    event: T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<&'a TaskStatsResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>
+}
+
+impl JsonReporter {
+
+    fn print<T: Serialize>(&self, event: T, stats: Option<&TaskStatsResult>, message: Option<&str>) {
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let line = JsonLine { ts, event, stats, message };
+
+        println!("{}", serde_json::to_string(&line).unwrap());
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum RunEvent {
+    RunFinished
+}
+
+impl Reporter for JsonReporter {
+
+    fn draw(&mut self, _data: &LayoutData) {}
+
+    fn task_event(&mut self, event: TaskEvent) {
+        self.print(event, None, None);
+    }
+
+    fn finish(&mut self, data: &LayoutData) {
+        self.print(RunEvent::RunFinished, Some(&data.tasks_stats_struct), Some(&data.log_message));
+    }
+}
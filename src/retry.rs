@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use rand::Rng;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use strum_macros::{EnumString, Display as StrumDisplay};
+
+use crate::executor::ExecCommandResult;
+
+#[derive(Clone, Copy, PartialEq, StrumDisplay, EnumString, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetryPolicyKind {
+    // same delay after every failure
+    #[strum(serialize = "fixed")]
+    Fixed,
+    // delay * attempt
+    #[strum(serialize = "linear")]
+    Linear,
+    // delay * 2 ^ (attempt - 1)
+    #[strum(serialize = "exponential")]
+    Exponential
+}
+
+pub struct RetryPolicy {
+    pub kind: RetryPolicyKind,
+    pub delay: u32,
+    // 0 means no limit
+    pub max_delay: u32,
+    // random part of delay, 0.2 means +-20%
+    pub jitter: f64
+}
+
+impl RetryPolicy {
+
+    // time to wait before retrying task which failed given number of times (starting from 1)
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let attempt = attempt.max(1);
+
+        let delay = match self.kind {
+            RetryPolicyKind::Fixed => self.delay as f64,
+            RetryPolicyKind::Linear => self.delay as f64 * attempt as f64,
+            RetryPolicyKind::Exponential => self.delay as f64 * 2f64.powi(attempt.min(64) as i32 - 1)
+        };
+
+        let delay = if self.max_delay > 0 { delay.min(self.max_delay as f64) } else { delay };
+
+        // spread retries of tasks which failed at the same time, capped delays too
+        let delay = if self.jitter > 0.0 {
+            let max = if self.max_delay > 0 { self.max_delay as f64 } else { f64::MAX };
+            let low = delay * (1.0 - self.jitter).max(0.0);
+            let high = (delay * (1.0 + self.jitter)).min(max);
+
+            rand::thread_rng().gen_range(low..=high)
+        } else {
+            delay
+        };
+
+        Duration::from_secs_f64(delay.clamp(0.0, u32::MAX as f64))
+    }
+}
+
+// Decides if failed task should be retried. Without rules every failure is retried
+#[derive(Default)]
+pub struct RetryRules {
+    pub retry_on_exit: Vec<i32>,
+    pub no_retry_on_exit: Vec<i32>,
+    pub stderr_pattern: Option<Regex>
+}
+
+impl RetryRules {
+
+    pub fn should_retry(&self, result: &ExecCommandResult) -> bool {
+        let exit_code = result.exit_code();
+
+        if exit_code.is_some_and(|code| self.no_retry_on_exit.contains(&code)) {
+            return false;
+        }
+
+        // when allow rules are given, failure must match at least one of them
+        if self.retry_on_exit.is_empty() && self.stderr_pattern.is_none() {
+            return true;
+        }
+
+        exit_code.is_some_and(|code| self.retry_on_exit.contains(&code))
+            || self.stderr_pattern.as_ref().is_some_and(|re| re.is_match(&result.stderr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn policy(kind: RetryPolicyKind, delay: u32, max_delay: u32, jitter: f64) -> RetryPolicy {
+        RetryPolicy { kind, delay, max_delay, jitter }
+    }
+
+    fn delays(policy: &RetryPolicy) -> Vec<u64> {
+        (1..=5).map(|attempt| policy.delay_for(attempt).as_secs()).collect()
+    }
+
+    fn result(exit_code: Option<i32>, stderr: &str) -> ExecCommandResult {
+        let mut result = crate::executor::spawn_error_result("job", "task", stderr);
+        // without exit code command was killed by signal
+        result.exit_status = ExitStatus::from_raw(exit_code.map_or(libc::SIGKILL, |code| code << 8));
+        result
+    }
+
+    fn rules(retry_on_exit: &[i32], no_retry_on_exit: &[i32], stderr_pattern: Option<&str>) -> RetryRules {
+        RetryRules {
+            retry_on_exit: retry_on_exit.to_vec(),
+            no_retry_on_exit: no_retry_on_exit.to_vec(),
+            stderr_pattern: stderr_pattern.map(|pattern| Regex::new(pattern).unwrap())
+        }
+    }
+
+    #[test]
+    fn every_failure_is_retried_without_rules() {
+        let rules = RetryRules::default();
+
+        assert!(rules.should_retry(&result(Some(1), "")));
+        assert!(rules.should_retry(&result(Some(127), "not found")));
+        assert!(rules.should_retry(&result(None, "")));
+    }
+
+    #[test]
+    fn deny_rule_takes_precedence() {
+        let allow_and_deny = rules(&[2, 75], &[2], Some("refused"));
+
+        assert!(!allow_and_deny.should_retry(&result(Some(2), "Connection refused")));
+        assert!(allow_and_deny.should_retry(&result(Some(75), "")));
+
+        let deny_only = rules(&[], &[2], None);
+
+        assert!(!deny_only.should_retry(&result(Some(2), "")));
+        assert!(deny_only.should_retry(&result(Some(1), "")));
+    }
+
+    #[test]
+    fn allow_rules_are_combined_with_or() {
+        let rules = rules(&[75], &[], Some("Connection (refused|reset)"));
+
+        assert!(rules.should_retry(&result(Some(75), "")));
+        assert!(rules.should_retry(&result(Some(1), "error: Connection reset by peer")));
+        assert!(!rules.should_retry(&result(Some(1), "Permission denied")));
+    }
+
+    #[test]
+    fn signal_killed_commands_match_only_stderr_rules() {
+        assert!(!rules(&[75], &[], None).should_retry(&result(None, "")));
+        assert!(rules(&[], &[75], None).should_retry(&result(None, "")));
+        assert!(rules(&[75], &[], Some("Killed")).should_retry(&result(None, "Killed")));
+    }
+
+    #[test]
+    fn delay_grows_with_attempt() {
+        assert_eq!(delays(&policy(RetryPolicyKind::Fixed, 3, 0, 0.0)), vec![3, 3, 3, 3, 3]);
+        assert_eq!(delays(&policy(RetryPolicyKind::Linear, 3, 0, 0.0)), vec![3, 6, 9, 12, 15]);
+        assert_eq!(delays(&policy(RetryPolicyKind::Exponential, 3, 0, 0.0)), vec![3, 6, 12, 24, 48]);
+        assert_eq!(policy(RetryPolicyKind::Linear, 3, 0, 0.0).delay_for(0).as_secs(), 3);
+        assert_eq!(policy(RetryPolicyKind::Exponential, 1, 0, 0.0).delay_for(1000).as_secs(), u32::MAX as u64);
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        assert_eq!(delays(&policy(RetryPolicyKind::Exponential, 3, 10, 0.0)), vec![3, 6, 10, 10, 10]);
+        assert_eq!(delays(&policy(RetryPolicyKind::Linear, 3, 7, 0.0)), vec![3, 6, 7, 7, 7]);
+    }
+
+    #[test]
+    fn jitter_stays_in_bounds() {
+        let policy = policy(RetryPolicyKind::Fixed, 1, 0, 0.2);
+        let delays: Vec<Duration> = (0..200).map(|_| policy.delay_for(1)).collect();
+
+        assert!(delays.iter().all(|delay| *delay >= Duration::from_millis(800) && *delay <= Duration::from_millis(1200)));
+        // sub-second jitter is kept
+        assert!(delays.iter().any(|delay| *delay != Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn capped_delays_are_jittered_below_cap() {
+        let policy = policy(RetryPolicyKind::Exponential, 10, 60, 0.5);
+        let delays: Vec<Duration> = (0..200).map(|_| policy.delay_for(10)).collect();
+
+        assert!(delays.iter().all(|delay| *delay >= Duration::from_secs(30) && *delay <= Duration::from_secs(60)));
+        assert!(delays.iter().any(|delay| *delay < Duration::from_secs(59)));
+    }
+}
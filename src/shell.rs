@@ -0,0 +1,158 @@
+use std::borrow::Cow;
+
+// Quote value, so sh treats it as a single word without any expansion
+pub fn quote(value: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-+=:,./@%".contains(c);
+
+    if !value.is_empty() && value.chars().all(is_safe) {
+        return Cow::Borrowed(value);
+    }
+
+    Cow::Owned(format!("'{}'", value.replace('\'', "'\\''")))
+}
+
+// Escape value placed between single quotes of command, e.g. echo '{{0}}'
+pub fn escape_single_quoted(value: &str) -> String {
+    value.replace('\'', "'\\''")
+}
+
+// Escape value placed between double quotes of command, so sh does not expand it
+pub fn escape_double_quoted(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+// Split command into words like sh does, but without any expansion. Supports single and double quotes and backslash escapes
+pub fn split(command: &str) -> anyhow::Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            },
+            '\'' => {
+                in_word = true;
+
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow::anyhow!("Unterminated single quote in command: {}", command))
+                    }
+                }
+            },
+            '"' => {
+                in_word = true;
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // inside double quotes backslash escapes only these characters
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some('\n') => {},
+                            Some(c) => { word.push('\\'); word.push(c); },
+                            None => return Err(anyhow::anyhow!("Unterminated double quote in command: {}", command))
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow::anyhow!("Unterminated double quote in command: {}", command))
+                    }
+                }
+            },
+            '\\' => {
+                in_word = true;
+
+                match chars.next() {
+                    Some('\n') => {},
+                    Some(c) => word.push(c),
+                    None => word.push('\\')
+                }
+            },
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+
+    if in_word {
+        words.push(word);
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    const VALUES: &[&str] = &["", "plain", "it's", "a b", "$(touch pwned)", "`id`", "line\nbreak", "\"q\" \\ $HOME", "'", "''\\''"];
+
+    // output of printf run by sh, with given text inserted into command
+    fn sh_printf(arg: &str) -> String {
+        let output = Command::new("sh").arg("-c").arg(format!("printf %s {}", arg)).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn quoted_value_is_passed_to_sh_unchanged() {
+        for value in VALUES {
+            assert_eq!(sh_printf(&quote(value)), *value);
+        }
+    }
+
+    #[test]
+    fn escaped_value_is_passed_to_sh_unchanged_inside_quotes() {
+        for value in VALUES {
+            assert_eq!(sh_printf(&format!("'{}'", escape_single_quoted(value))), *value);
+            assert_eq!(sh_printf(&format!("\"{}\"", escape_double_quoted(value))), *value);
+        }
+    }
+
+    #[test]
+    fn safe_values_are_not_quoted() {
+        assert_eq!(quote("file-1.txt"), "file-1.txt");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a b"), "'a b'");
+    }
+
+    #[test]
+    fn quoted_value_is_single_word_for_split() {
+        for value in VALUES {
+            assert_eq!(split(&format!("echo {}", quote(value))).unwrap(), vec!["echo".to_owned(), value.to_string()]);
+        }
+    }
+
+    #[test]
+    fn splits_words_with_quotes_and_backslashes() {
+        assert_eq!(split("  a  'b c'\td\"e f\"g ").unwrap(), vec!["a", "b c", "de fg"]);
+        assert_eq!(split(r#"a\ b "c\"d\$e\x" 'f\g'"#).unwrap(), vec!["a b", "c\"d$e\\x", "f\\g"]);
+        assert_eq!(split("'' \"\"").unwrap(), vec!["", ""]);
+        assert_eq!(split("a\\\nb").unwrap(), vec!["ab"]);
+        assert!(split("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        assert!(split("echo 'a").is_err());
+        assert!(split("echo \"a").is_err());
+        assert!(split("echo \"a\\\"").is_err());
+    }
+}
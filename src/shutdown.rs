@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use strum_macros::{EnumString, Display as StrumDisplay};
+
+use crate::executor::RunningTasks;
+
+#[derive(Clone, Copy, PartialEq, StrumDisplay, EnumString, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownMode {
+    // wait for running tasks to finish
+    #[strum(serialize = "drain")]
+    Drain,
+    // send SIGTERM to running tasks and SIGKILL after grace period
+    #[strum(serialize = "terminate")]
+    Terminate
+}
+
+// Flag which is raised by 'q' key press or by SIGINT, SIGTERM and SIGHUP
+#[derive(Clone, Default)]
+pub struct ShutdownSignal {
+    flag: Arc<AtomicBool>
+}
+
+impl ShutdownSignal {
+
+    pub fn register_os_signals(&self) -> anyhow::Result<()> {
+        for signal in &[SIGINT, SIGTERM, SIGHUP] {
+            signal_hook::flag::register(*signal, Arc::clone(&self.flag))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn request(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    // Returns true if shutdown was requested since last call
+    pub fn take_request(&self) -> bool {
+        self.flag.swap(false, Ordering::SeqCst)
+    }
+}
+
+pub struct Shutdown {
+    mode: ShutdownMode,
+    grace_period: Duration,
+    started_at: Option<Instant>,
+    terminated_at: Option<Instant>,
+    killed: bool
+}
+
+impl Shutdown {
+
+    pub fn new(mode: ShutdownMode, grace_period: Duration) -> Shutdown {
+        Shutdown {
+            mode,
+            grace_period,
+            started_at: None,
+            terminated_at: None,
+            killed: false
+        }
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    // Start shutdown. Second request while draining escalates to terminate
+    pub fn request(&mut self, running: &RunningTasks) {
+        if self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+
+            if self.mode == ShutdownMode::Drain {
+                return;
+            }
+        }
+
+        if self.terminated_at.is_none() {
+            self.terminated_at = Some(Instant::now());
+            running.signal_all(libc::SIGTERM);
+        }
+    }
+
+    // Kill tasks which ignored SIGTERM during grace period
+    pub fn tick(&mut self, running: &RunningTasks) {
+        if let Some(terminated_at) = self.terminated_at {
+            if !self.killed && terminated_at.elapsed() >= self.grace_period {
+                self.killed = true;
+                running.signal_all(libc::SIGKILL);
+            }
+        }
+    }
+
+    pub fn status_message(&self, running: usize) -> String {
+        match (self.terminated_at, self.killed) {
+            (None, _) => format!("Shutting down. Waiting for {} running tasks to complete... Press 'q' again to terminate them", running),
+            (Some(_), false) => format!("Shutting down. Sent SIGTERM to {} running tasks, waiting {} seconds...", running, self.grace_period.as_secs()),
+            (Some(_), true) => format!("Shutting down. Sent SIGKILL to {} running tasks...", running)
+        }
+    }
+}
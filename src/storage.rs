@@ -1,55 +1,91 @@
 use std::str::FromStr;
+use std::time::Duration;
 
-use csv::StringRecord;
-use rusqlite::{Connection, OptionalExtension};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Transaction};
 use strum_macros::{EnumString, Display as StrumDisplay};
 use serde::{Serialize};
-use crate::ExecCommandResult;
+use crate::config::RunConfig;
+use crate::executor::ExecCommandResult;
+use crate::migrations;
 
 pub fn create_database(path: &str) -> anyhow::Result<ConnHandle> {
-    let connection = Connection::open(path)?;
-    
-    // create schema
-    connection.execute(
-        "CREATE TABLE IF NOT EXISTS tasks (
-             task_id VARCHAR(255) primary key,
-             status VARCHAR(255) not null,
-             command TEXT,
-             elapsed_time VARCHAR(255),
-             reshedule_count INT not null,
-             ignore_till INT null,
-             stdout TEXT,
-             stderr TEXT
-         )",
-        [],
-    )?;
+    let mut connection = Connection::open(path)?;
+
+    // create or upgrade schema
+    migrations::migrate(&mut connection)?;
 
     let handle = ConnHandle {
         conn: connection
     };
 
-    return Ok(handle);
+    Ok(handle)
+}
+
+// Changes made through handle are rolled back if transaction is dropped without commit
+pub fn begin_transaction(handle: &ConnHandle) -> rusqlite::Result<Transaction<'_>> {
+    handle.conn.unchecked_transaction()
 }
 
-pub fn get_next_task(handle: &ConnHandle, max_tries: u32) -> Option<String> {
+// Returns next task to process. Tasks of full stages and groups are not returned
+pub fn get_next_task(handle: &ConnHandle, max_tries: u32, full_stages: &[usize], full_groups: &[String]) -> Option<NextTask> {
+    let stages = full_stages.iter().map(|stage| stage.to_string()).collect::<Vec<String>>().join(",");
+    let groups = (0..full_groups.len()).map(|idx| format!("?{}", idx + 5)).collect::<Vec<String>>().join(",");
+
+    let mut params = vec![TaskStatus::New.to_string(), TaskStatus::Resheduled.to_string(), max_tries.to_string(), TaskStatus::Completed.to_string()];
+    params.extend(full_groups.iter().cloned());
+
     handle.conn.query_row(
-        "SELECT task_id FROM tasks WHERE status = ?1 OR (status = ?2 AND reshedule_count <= ?3 AND CAST(strftime('%s', 'now') as INT) > ignore_till ) LIMIT 1",
-        [&TaskStatus::New.to_string(), &TaskStatus::Resheduled.to_string(), &max_tries.to_string()],
-        |row| row.get(0)
+        &format!(
+            "SELECT task_id, stage, task_group FROM tasks WHERE (status = ?1 OR (status = ?2 AND reshedule_count <= ?3 AND (julianday('now') - 2440587.5) * 86400.0 > ignore_till ))
+             AND NOT EXISTS (SELECT 1 FROM task_deps d JOIN tasks p ON p.task_id = d.depends_on WHERE d.task_id = tasks.task_id AND p.status != ?4)
+             AND stage NOT IN ({}) AND (task_group IS NULL OR task_group NOT IN ({}))
+             ORDER BY priority DESC, rowid LIMIT 1",
+            stages, groups
+        ),
+        params_from_iter(params),
+        |row| Ok(NextTask { task_id: row.get(0)?, stage: row.get(1)?, group: row.get(2)? })
     ).optional().unwrap()
 }
 
 pub fn get_number_of_incomplete_tasks(handle: &ConnHandle) -> rusqlite::Result<usize> {
     handle.conn.query_row(
-        "SELECT COUNT(task_id) FROM tasks WHERE status != ?1 AND status != ?2 AND status != ?3",
-        [&TaskStatus::Completed.to_string(), &TaskStatus::Error.to_string(), &TaskStatus::Aborted.to_string()],
+        "SELECT COUNT(task_id) FROM tasks WHERE status NOT IN (?1, ?2, ?3, ?4, ?5)",
+        [
+            &TaskStatus::Completed.to_string(), &TaskStatus::Error.to_string(), &TaskStatus::Aborted.to_string(),
+            &TaskStatus::Timeout.to_string(), &TaskStatus::Skipped.to_string()
+        ],
         |row| row.get(0)
     )
 }
 
-pub fn import_task(handle: &ConnHandle, record: &StringRecord, command_template: &str) {
-    let task = &record[0];
+// number of incomplete tasks by status
+pub fn get_incomplete_tasks_by_status(handle: &ConnHandle) -> rusqlite::Result<Vec<(String, usize)>> {
+    let mut stmt = handle.conn.prepare(
+        "SELECT status, COUNT(task_id) FROM tasks WHERE status NOT IN (?1, ?2, ?3, ?4, ?5) GROUP BY status ORDER BY status"
+    )?;
+    let rows = stmt.query_map(
+        [
+            &TaskStatus::Completed.to_string(), &TaskStatus::Error.to_string(), &TaskStatus::Aborted.to_string(),
+            &TaskStatus::Timeout.to_string(), &TaskStatus::Skipped.to_string()
+        ],
+        |row| Ok((row.get(0)?, row.get(1)?))
+    )?;
+
+    rows.collect()
+}
+
+// rescheduled tasks which wait for retry delay
+pub fn get_number_of_waiting_tasks(handle: &ConnHandle, max_tries: u32) -> rusqlite::Result<usize> {
+    handle.conn.query_row(
+        "SELECT COUNT(task_id) FROM tasks WHERE status = ?1 AND reshedule_count <= ?2",
+        params![&TaskStatus::Resheduled.to_string(), max_tries],
+        |row| row.get(0)
+    )
+}
 
+// Each command is a pipeline stage, task starts with the first one. Args are values of tasks file row, used to render command again when task is dispatched
+#[allow(clippy::too_many_arguments)]
+pub fn import_task(handle: &ConnHandle, task: &str, args: &[&str], commands: &[String], timeout: Option<u32>, priority: i32, group: Option<&str>, depends_on: &[&str]) {
     if task.is_empty() {
         return;
     }
@@ -60,19 +96,135 @@ pub fn import_task(handle: &ConnHandle, record: &StringRecord, command_template:
         return;
     }
 
-    // for backward compatibility
-    let mut command_to_execute = command_template.replace("{{task}}", task);
-    
-    // interpolate all columns by column index
-    for i in 0..record.len() {
-        command_to_execute = command_to_execute.replace(&format!("{{{{{}}}}}", i), &record[i]);
-    }
+    // commands of next stages are kept until task gets to them
+    let stage_commands = if commands.len() > 1 { Some(serde_json::to_string(&commands).unwrap()) } else { None };
 
     // insert new task
     // println!("Inserting new task {}", task);
-    handle.conn.execute("INSERT INTO tasks (task_id, status, command, reshedule_count) VALUES (?1, ?2, ?3, 0)", [task, &TaskStatus::New.to_string(), &command_to_execute]).unwrap();
+    handle.conn.execute(
+        "INSERT INTO tasks (task_id, status, command, reshedule_count, timeout, priority, stage, stage_commands, task_group, task_args, created_at)
+         VALUES (?1, ?2, ?3, 0, ?4, ?5, 0, ?6, ?7, ?8, CAST(strftime('%s', 'now') as INT))",
+        params![task, &TaskStatus::New.to_string(), &commands[0], timeout, priority, stage_commands, group, serde_json::to_string(args).unwrap()]
+    ).unwrap();
+
+    for parent in depends_on {
+        handle.conn.execute("INSERT OR IGNORE INTO task_deps (task_id, depends_on) VALUES (?1, ?2)", [task, parent]).unwrap();
+    }
+}
+
+pub fn get_task_stage(handle: &ConnHandle, task_id: &str) -> Option<usize> {
+    handle.conn.query_row("SELECT stage FROM tasks WHERE task_id = ?1", [task_id], |row| row.get(0)).optional().unwrap()
+}
+
+pub fn get_task_group(handle: &ConnHandle, task_id: &str) -> Option<String> {
+    handle.conn.query_row("SELECT task_group FROM tasks WHERE task_id = ?1", [task_id], |row| row.get(0)).optional().unwrap().flatten()
+}
+
+// Move task to next pipeline stage. Returns false if task is at the last stage
+pub fn advance_task_stage(handle: &ConnHandle, task_id: &str) -> anyhow::Result<bool> {
+    let (stage, stage_commands): (usize, Option<String>) = handle.conn.query_row(
+        "SELECT stage, stage_commands FROM tasks WHERE task_id = ?1", [task_id], |row| Ok((row.get(0)?, row.get(1)?))
+    )?;
+
+    let commands: Vec<String> = match stage_commands {
+        Some(json) => serde_json::from_str(&json)?,
+        None => return Ok(false)
+    };
+
+    let command = match commands.get(stage + 1) {
+        Some(command) => command,
+        None => return Ok(false)
+    };
+
+    handle.conn.execute(
+        "UPDATE tasks SET status = ?1, stage = ?2, command = ?3, reshedule_count = 0, ignore_till = NULL WHERE task_id = ?4",
+        params![&TaskStatus::New.to_string(), stage + 1, command, task_id]
+    )?;
+
+    Ok(true)
+}
+
+// None for tasks imported by versions of workman which did not store them
+pub fn get_task_args(handle: &ConnHandle, task_id: &str) -> anyhow::Result<Option<Vec<String>>> {
+    let args: Option<String> = handle.conn.query_row("SELECT task_args FROM tasks WHERE task_id = ?1", [task_id], |row| row.get(0)).optional()?.flatten();
+
+    match args {
+        Some(args) => Ok(Some(serde_json::from_str(&args)?)),
+        None => Ok(None)
+    }
+}
+
+// tasks which are not completed yet, with their current stage
+pub fn get_pending_tasks(handle: &ConnHandle) -> rusqlite::Result<Vec<(String, usize)>> {
+    let mut stmt = handle.conn.prepare("SELECT task_id, stage FROM tasks WHERE status != ?1 ORDER BY rowid")?;
+    let rows = stmt.query_map([&TaskStatus::Completed.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    rows.collect()
+}
+
+// replace commands of all stages, task keeps its current stage
+pub fn update_task_commands(handle: &ConnHandle, task_id: &str, stage: usize, commands: &[String]) -> rusqlite::Result<usize> {
+    let stage_commands = if commands.len() > 1 { Some(serde_json::to_string(&commands).unwrap()) } else { None };
+    handle.conn.execute("UPDATE tasks SET command = ?1, stage_commands = ?2 WHERE task_id = ?3", params![&commands[stage], stage_commands, task_id])
+}
+
+pub fn get_task_dependencies(handle: &ConnHandle) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = handle.conn.prepare("SELECT task_id, depends_on FROM task_deps")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    rows.collect()
+}
+
+// first dependency which references task missing in database
+pub fn find_unknown_dependency(handle: &ConnHandle) -> rusqlite::Result<Option<(String, String)>> {
+    handle.conn.query_row(
+        "SELECT d.task_id, d.depends_on FROM task_deps d LEFT JOIN tasks t ON t.task_id = d.depends_on WHERE t.task_id IS NULL LIMIT 1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?))
+    ).optional()
+}
+
+// Tasks which depend (directly or not) on failed task can never run, so they are skipped. Returns ids of skipped tasks
+pub fn skip_blocked_tasks(handle: &ConnHandle) -> rusqlite::Result<Vec<String>> {
+    let mut skipped = vec![];
+
+    loop {
+        let blocked = get_task_ids(
+            handle,
+            "SELECT task_id FROM tasks WHERE status IN (?2, ?3)
+             AND EXISTS (SELECT 1 FROM task_deps d JOIN tasks p ON p.task_id = d.depends_on WHERE d.task_id = tasks.task_id AND p.status IN (?4, ?5, ?6, ?1))",
+            &[
+                &TaskStatus::Skipped.to_string(), &TaskStatus::New.to_string(), &TaskStatus::Resheduled.to_string(),
+                &TaskStatus::Error.to_string(), &TaskStatus::Timeout.to_string(), &TaskStatus::Aborted.to_string()
+            ]
+        )?;
+
+        if blocked.is_empty() {
+            return Ok(skipped);
+        }
+
+        for task_id in &blocked {
+            handle.conn.execute("UPDATE tasks SET status = ?1 WHERE task_id = ?2", [&TaskStatus::Skipped.to_string(), task_id])?;
+        }
+
+        skipped.extend(blocked);
+    }
+}
+
+// skipped tasks get another chance when their failed parents are processed again. Returns ids of tasks set to new
+pub fn unskip_tasks(handle: &ConnHandle) -> rusqlite::Result<Vec<String>> {
+    let skipped = get_task_ids(handle, "SELECT task_id FROM tasks WHERE status = ?1", &[&TaskStatus::Skipped.to_string()])?;
+    handle.conn.execute("UPDATE tasks SET status = ?1 WHERE status = ?2", [&TaskStatus::New.to_string(), &TaskStatus::Skipped.to_string()])?;
+
+    Ok(skipped)
 }
 
+fn get_task_ids(handle: &ConnHandle, query: &str, params: &[&String]) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = handle.conn.prepare(query)?;
+    let rows = stmt.query_map(params_from_iter(params), |row| row.get(0))?;
+
+    rows.collect()
+}
 
 pub fn get_task_status(handle: &ConnHandle, task_id: &str) -> Option<String> {
     handle.conn.query_row("SELECT status FROM tasks WHERE task_id = ?1", [task_id], |row| row.get(0)).optional().unwrap()
@@ -82,27 +234,124 @@ pub fn get_task_command(handle: &ConnHandle, task_id: &str) -> Option<String> {
     handle.conn.query_row("SELECT command FROM tasks WHERE task_id = ?1", [task_id], |row| row.get(0)).optional().unwrap()
 }
 
+pub fn get_task_timeout(handle: &ConnHandle, task_id: &str) -> Option<u32> {
+    handle.conn.query_row("SELECT timeout FROM tasks WHERE task_id = ?1", [task_id], |row| row.get(0)).optional().unwrap().flatten()
+}
+
 pub fn get_task_reshedule_count(handle: &ConnHandle, task_id: &str) -> Option<u32> {
     handle.conn.query_row("SELECT reshedule_count FROM tasks WHERE task_id = ?1", [task_id], |row| row.get(0)).optional().unwrap()
 }
 
+pub fn mark_task_scheduled(handle: &ConnHandle, task_id: &str, run_id: i64) -> rusqlite::Result<usize> {
+    handle.conn.execute("UPDATE tasks SET status = ?1, run_id = ?2 WHERE task_id = ?3 LIMIT 1", params![&TaskStatus::Scheduled.to_string(), run_id, task_id])
+}
+
+pub fn mark_task_processing(handle: &ConnHandle, task_id: &str) -> rusqlite::Result<usize> {
+    handle.conn.execute(
+        "UPDATE tasks SET status = ?1, started_at = CAST(strftime('%s', 'now') as INT), finished_at = NULL WHERE task_id = ?2 LIMIT 1",
+        [&TaskStatus::Processing.to_string(), task_id]
+    )
+}
+
 pub fn set_task_status(handle: &ConnHandle, task_id: &str, status: &TaskStatus) -> rusqlite::Result<usize> {
     handle.conn.execute("UPDATE tasks SET status = ?1 WHERE task_id = ?2 LIMIT 1", [&status.to_string(), task_id])
 }
 
-pub fn reshedule_task(handle: &ConnHandle, task_id: &str, seconds: u32) -> rusqlite::Result<usize> {
+pub fn set_task_priority(handle: &ConnHandle, task_id: &str, priority: i32) -> rusqlite::Result<usize> {
+    handle.conn.execute("UPDATE tasks SET priority = ?1 WHERE task_id = ?2 LIMIT 1", params![priority, task_id])
+}
+
+// ignore_till is fractional unix time, so retry delay jitter is not lost to rounding
+pub fn reshedule_task(handle: &ConnHandle, task_id: &str, delay: Duration) -> rusqlite::Result<usize> {
     handle.conn.execute(
-        "UPDATE tasks SET status = ?1, reshedule_count = reshedule_count + 1, ignore_till = CAST(strftime('%s', 'now') as INT) + ?3  WHERE task_id = ?2 LIMIT 1", 
-        [&TaskStatus::Resheduled.to_string(), task_id, &seconds.to_string()]
+        "UPDATE tasks SET status = ?1, reshedule_count = reshedule_count + 1, ignore_till = (julianday('now') - 2440587.5) * 86400.0 + ?3  WHERE task_id = ?2 LIMIT 1",
+        params![&TaskStatus::Resheduled.to_string(), task_id, delay.as_secs_f64()]
     )
 }
 
-pub fn update_task_from_result(handle: &ConnHandle, result: &ExecCommandResult) -> rusqlite::Result<usize> {
-    let status = if result.exit_status.success() { TaskStatus::Completed } else { TaskStatus::Error };
+pub fn status_from_result(result: &ExecCommandResult, success: bool) -> TaskStatus {
+    if success {
+        TaskStatus::Completed
+    } else if result.timed_out {
+        TaskStatus::Timeout
+    } else if result.aborted {
+        TaskStatus::Aborted
+    } else {
+        TaskStatus::Error
+    }
+}
 
+pub fn update_task_from_result(handle: &ConnHandle, result: &ExecCommandResult, status: &TaskStatus) -> rusqlite::Result<usize> {
     handle.conn.execute(
-        "UPDATE tasks SET status = ?1, command = ?2, stdout = ?3, stderr = ?4, elapsed_time = ?5 WHERE task_id = ?6 LIMIT 1", 
-        [&status.to_string(), &result.command, &result.stdout, &result.stderr, &result.elapsed_time_ms.to_string(), &result.task_id])
+        "UPDATE tasks SET status = ?1, command = ?2, stdout = ?3, stderr = ?4, elapsed_ms = ?5, exit_code = ?6, signal = ?7, started_at = ?8, finished_at = ?9,
+         stdout_path = ?10, stderr_path = ?11
+         WHERE task_id = ?12 LIMIT 1", 
+        params![
+            &status.to_string(), &result.command, &result.stdout, &result.stderr, result.elapsed_time_ms as i64,
+            result.exit_code(), result.signal(), result.started_at, result.finished_at,
+            &result.stdout_path, &result.stderr_path, &result.task_id
+        ])
+}
+
+// store output without finishing task, e.g. when task moves to next pipeline stage
+pub fn update_task_output(handle: &ConnHandle, result: &ExecCommandResult) -> rusqlite::Result<usize> {
+    handle.conn.execute(
+        "UPDATE tasks SET stdout = ?1, stderr = ?2, elapsed_ms = ?3, exit_code = ?4, signal = ?5, finished_at = ?6, stdout_path = ?7, stderr_path = ?8
+         WHERE task_id = ?9 LIMIT 1",
+        params![
+            &result.stdout, &result.stderr, result.elapsed_time_ms as i64, result.exit_code(), result.signal(), result.finished_at,
+            &result.stdout_path, &result.stderr_path, &result.task_id
+        ])
+}
+
+// attempts are numbered within pipeline stage
+pub fn insert_attempt(handle: &ConnHandle, run_id: i64, stage: usize, status: &TaskStatus, result: &ExecCommandResult) -> rusqlite::Result<usize> {
+    handle.conn.execute(
+        "INSERT INTO attempts (task_id, run_id, stage, attempt, status, worker_id, started_at, finished_at, exit_code, signal, elapsed_ms, stdout, stderr)
+         VALUES (?1, ?2, ?3, (SELECT COUNT(id) + 1 FROM attempts WHERE task_id = ?1 AND stage = ?3), ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            &result.task_id, run_id, stage as i64, &status.to_string(), result.worker_id as i64, result.started_at, result.finished_at,
+            result.exit_code(), result.signal(), result.elapsed_time_ms as i64, &result.stdout, &result.stderr
+        ]
+    )
+}
+
+pub fn get_task_attempts(handle: &ConnHandle, task_id: &str) -> anyhow::Result<Vec<AttemptRecord>> {
+    let mut stmt = handle.conn.prepare(
+        "SELECT attempt, run_id, worker_id, started_at, finished_at, exit_code, signal, elapsed_ms, stdout, stderr, stage, status
+         FROM attempts WHERE task_id = ?1 ORDER BY id"
+    )?;
+
+    let rows = stmt.query_map([task_id], |row| {
+        Ok(AttemptRecord {
+            attempt: row.get(0)?,
+            run_id: row.get(1)?,
+            worker_id: row.get(2)?,
+            started_at: row.get(3)?,
+            finished_at: row.get(4)?,
+            exit_code: row.get(5)?,
+            signal: row.get(6)?,
+            elapsed_ms: row.get(7)?,
+            stdout: row.get(8)?,
+            stderr: row.get(9)?,
+            stage: row.get(10)?,
+            status: row.get(11)?
+        })
+    })?;
+
+    Ok(rows.collect::<rusqlite::Result<Vec<AttemptRecord>>>()?)
+}
+
+// failed tasks get all tries again, returns their ids
+pub fn requeue_failed_tasks(handle: &ConnHandle) -> rusqlite::Result<Vec<String>> {
+    let failed = get_task_ids(handle, "SELECT task_id FROM tasks WHERE status IN (?1, ?2)", &[&TaskStatus::Error.to_string(), &TaskStatus::Timeout.to_string()])?;
+
+    handle.conn.execute(
+        "UPDATE tasks SET status = ?1, reshedule_count = 0, ignore_till = NULL WHERE status IN (?2, ?3)",
+        [&TaskStatus::New.to_string(), &TaskStatus::Error.to_string(), &TaskStatus::Timeout.to_string()]
+    )?;
+
+    Ok(failed)
 }
 
 pub fn mark_pending_tasks_as_aborted(handle: &ConnHandle) -> rusqlite::Result<usize> {
@@ -113,6 +362,149 @@ pub fn mark_scheduled_tasks_as_new(handle: &ConnHandle) -> rusqlite::Result<usiz
     handle.conn.execute("UPDATE tasks SET status = ?1 WHERE status = ?2", [&TaskStatus::New.to_string(), &TaskStatus::Scheduled.to_string()])
 }
 
+// tasks left in processing state by crashed run
+pub fn mark_processing_tasks_as_new(handle: &ConnHandle) -> rusqlite::Result<usize> {
+    handle.conn.execute("UPDATE tasks SET status = ?1 WHERE status = ?2", [&TaskStatus::New.to_string(), &TaskStatus::Processing.to_string()])
+}
+
+pub fn recover_interrupted_tasks(handle: &ConnHandle) -> rusqlite::Result<usize> {
+    handle.conn.execute(
+        "UPDATE tasks SET status = ?1 WHERE status IN (?2, ?3, ?4)",
+        [&TaskStatus::New.to_string(), &TaskStatus::Scheduled.to_string(), &TaskStatus::Processing.to_string(), &TaskStatus::Aborted.to_string()]
+    )
+}
+
+pub fn save_run_config(handle: &ConnHandle, config: &RunConfig) -> anyhow::Result<()> {
+    let value = serde_json::to_string(config)?;
+    handle.conn.execute("INSERT OR REPLACE INTO settings (name, value) VALUES ('run_config', ?1)", [&value])?;
+
+    Ok(())
+}
+
+pub fn get_run_config(handle: &ConnHandle) -> anyhow::Result<Option<RunConfig>> {
+    let value: Option<String> = handle.conn.query_row("SELECT value FROM settings WHERE name = 'run_config'", [], |row| row.get(0)).optional()?;
+
+    match value {
+        Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+        None => Ok(None)
+    }
+}
+
+// header row of tasks file, used to resolve named placeholders
+pub fn save_task_headers(handle: &ConnHandle, headers: Option<&[String]>) -> anyhow::Result<()> {
+    match headers {
+        Some(headers) => handle.conn.execute("INSERT OR REPLACE INTO settings (name, value) VALUES ('task_headers', ?1)", [&serde_json::to_string(headers)?])?,
+        None => handle.conn.execute("DELETE FROM settings WHERE name = 'task_headers'", [])?
+    };
+
+    Ok(())
+}
+
+pub fn get_task_headers(handle: &ConnHandle) -> anyhow::Result<Option<Vec<String>>> {
+    let value: Option<String> = handle.conn.query_row("SELECT value FROM settings WHERE name = 'task_headers'", [], |row| row.get(0)).optional()?;
+
+    match value {
+        Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+        None => Ok(None)
+    }
+}
+
+pub fn start_run(handle: &ConnHandle, command: &str, config: &RunConfig, host: &str) -> anyhow::Result<i64> {
+    let config = serde_json::to_string(config)?;
+
+    handle.conn.execute(
+        "INSERT INTO runs (command, config, host, started_at) VALUES (?1, ?2, ?3, CAST(strftime('%s', 'now') as INT))",
+        [command, &config, host]
+    )?;
+
+    Ok(handle.conn.last_insert_rowid())
+}
+
+pub fn finish_run(handle: &ConnHandle, run_id: i64, exit_reason: &str) -> rusqlite::Result<usize> {
+    handle.conn.execute(
+        "UPDATE runs SET finished_at = CAST(strftime('%s', 'now') as INT), exit_reason = ?1 WHERE id = ?2",
+        params![exit_reason, run_id]
+    )
+}
+
+pub fn get_runs(handle: &ConnHandle) -> anyhow::Result<Vec<RunRecord>> {
+    let mut stmt = handle.conn.prepare(
+        "SELECT r.id, r.command, r.config, r.host, r.started_at, r.finished_at, r.exit_reason, (SELECT COUNT(task_id) FROM tasks t WHERE t.run_id = r.id)
+         FROM runs r ORDER BY r.id"
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        let config: String = row.get(2)?;
+
+        Ok(RunRecord {
+            id: row.get(0)?,
+            command: row.get(1)?,
+            config: serde_json::from_str(&config).unwrap_or(serde_json::Value::Null),
+            host: row.get(3)?,
+            started_at: row.get(4)?,
+            finished_at: row.get(5)?,
+            exit_reason: row.get(6)?,
+            tasks: row.get(7)?
+        })
+    })?;
+
+    Ok(rows.collect::<rusqlite::Result<Vec<RunRecord>>>()?)
+}
+
+pub fn get_task_list(handle: &ConnHandle, filter: Option<&TaskStatus>, limit: u32) -> anyhow::Result<Vec<TaskListItem>> {
+    // elapsed time of running tasks is calculated from start time
+    let columns = "task_id, status, reshedule_count + 1,
+        CASE WHEN status = 'processing' THEN (CAST(strftime('%s', 'now') as INT) - started_at) * 1000 ELSE elapsed_ms END, stage";
+
+    let mut stmt = match filter {
+        Some(_) => handle.conn.prepare(&format!(
+            "SELECT {} FROM tasks WHERE status = ?1 ORDER BY COALESCE(finished_at, started_at, created_at) DESC, rowid DESC LIMIT ?2", columns
+        ))?,
+        None => handle.conn.prepare(&format!(
+            "SELECT {} FROM tasks WHERE status = ?1 OR finished_at IS NOT NULL ORDER BY status = ?1 DESC, finished_at DESC LIMIT ?2", columns
+        ))?
+    };
+
+    let status = filter.unwrap_or(&TaskStatus::Processing).to_string();
+
+    let rows = stmt.query_map(params![status, limit], |row| {
+        let status: String = row.get(1)?;
+
+        Ok(TaskListItem {
+            task_id: row.get(0)?,
+            status: TaskStatus::from_str(&status).unwrap_or(TaskStatus::New),
+            attempt: row.get(2)?,
+            elapsed_ms: row.get(3)?,
+            stage: row.get(4)?
+        })
+    })?;
+
+    Ok(rows.collect::<rusqlite::Result<Vec<TaskListItem>>>()?)
+}
+
+pub fn get_task_details(handle: &ConnHandle, task_id: &str) -> anyhow::Result<Option<TaskDetails>> {
+    let details = handle.conn.query_row(
+        "SELECT task_id, status, command, exit_code, stdout, stderr, stdout_path, stderr_path FROM tasks WHERE task_id = ?1",
+        [task_id],
+        |row| {
+            let status: String = row.get(1)?;
+
+            Ok(TaskDetails {
+                task_id: row.get(0)?,
+                status: TaskStatus::from_str(&status).unwrap_or(TaskStatus::New),
+                command: row.get(2)?,
+                exit_code: row.get(3)?,
+                stdout: row.get(4)?,
+                stderr: row.get(5)?,
+                stdout_path: row.get(6)?,
+                stderr_path: row.get(7)?
+            })
+        }
+    ).optional()?;
+
+    Ok(details)
+}
+
 pub fn get_stats_struct(handle: &ConnHandle) -> anyhow::Result<TaskStatsResult> {
     let mut stmt = handle.conn.prepare("SELECT status, COUNT(status) cnt FROM tasks GROUP BY status")?;
     let mut result = TaskStatsResult::default();
@@ -131,7 +523,9 @@ pub fn get_stats_struct(handle: &ConnHandle) -> anyhow::Result<TaskStatsResult>
             TaskStatus::Error       => result.error += count,
             TaskStatus::Processing  => result.processing += count,
             TaskStatus::Scheduled   => result.scheduled += count,
-            TaskStatus::Resheduled  => result.rescheduled += count
+            TaskStatus::Resheduled  => result.rescheduled += count,
+            TaskStatus::Timeout     => result.timeout += count,
+            TaskStatus::Skipped     => result.skipped += count
         }
 
         result.total += count;
@@ -144,7 +538,7 @@ pub struct ConnHandle {
     conn: Connection
 }
 
-#[derive(StrumDisplay, EnumString)]
+#[derive(Clone, Copy, PartialEq, Debug, StrumDisplay, EnumString)]
 pub enum TaskStatus {
     #[strum(serialize = "new")]
     New,
@@ -159,7 +553,12 @@ pub enum TaskStatus {
     #[strum(serialize = "error")]
     Error,
     #[strum(serialize = "aborted")]
-    Aborted
+    Aborted,
+    #[strum(serialize = "timeout")]
+    Timeout,
+    // task depends on task which failed
+    #[strum(serialize = "skipped")]
+    Skipped
 }
 
 #[derive(Default, Debug, Serialize)]
@@ -171,5 +570,62 @@ pub struct TaskStatsResult {
     pub completed: u64,
     pub error: u64,
     pub aborted: u64,
+    pub timeout: u64,
+    pub skipped: u64,
     pub total: u64
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunRecord {
+    pub id: i64,
+    pub command: String,
+    pub config: serde_json::Value,
+    pub host: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub exit_reason: Option<String>,
+    // number of tasks which were executed last time in this run
+    pub tasks: u64
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttemptRecord {
+    pub attempt: u32,
+    pub run_id: Option<i64>,
+    pub worker_id: Option<u32>,
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub elapsed_ms: u64,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    // unknown for attempts recorded by older versions
+    pub stage: Option<u32>,
+    pub status: Option<String>
+}
+
+pub struct NextTask {
+    pub task_id: String,
+    pub stage: usize,
+    pub group: Option<String>
+}
+
+pub struct TaskListItem {
+    pub task_id: String,
+    pub status: TaskStatus,
+    pub attempt: u32,
+    pub elapsed_ms: Option<i64>,
+    pub stage: usize
+}
+
+pub struct TaskDetails {
+    pub task_id: String,
+    pub status: TaskStatus,
+    pub command: Option<String>,
+    pub exit_code: Option<i32>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub stdout_path: Option<String>,
+    pub stderr_path: Option<String>
+}
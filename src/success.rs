@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::executor::ExecCommandResult;
+
+// Decides if finished command is successful. By default only exit code 0 is success
+pub struct SuccessCriteria {
+    pub exit_codes: Vec<i32>,
+    // stdout must match
+    pub stdout_pattern: Option<Regex>,
+    // stderr must not match
+    pub stderr_pattern: Option<Regex>,
+    pub json_field: Option<JsonFieldCheck>
+}
+
+impl SuccessCriteria {
+
+    pub fn is_success(&self, result: &ExecCommandResult) -> bool {
+        if result.timed_out || result.aborted {
+            return false;
+        }
+
+        if !result.exit_code().is_some_and(|code| self.exit_codes.contains(&code)) {
+            return false;
+        }
+
+        if self.stdout_pattern.as_ref().is_some_and(|re| !re.is_match(&result.stdout)) {
+            return false;
+        }
+
+        if self.stderr_pattern.as_ref().is_some_and(|re| re.is_match(&result.stderr)) {
+            return false;
+        }
+
+        if self.json_field.as_ref().is_some_and(|check| !check.matches(&result.stdout)) {
+            return false;
+        }
+
+        true
+    }
+}
+
+// Field of JSON object printed to stdout which must have given value, e.g. "result.status=ok"
+pub struct JsonFieldCheck {
+    path: Vec<String>,
+    value: String
+}
+
+impl JsonFieldCheck {
+
+    fn matches(&self, stdout: &str) -> bool {
+        // whole stdout or its last line, so commands can log something before printing result
+        let json = serde_json::from_str::<Value>(stdout.trim()).ok().or_else(|| {
+            stdout.lines().rev().find(|line| !line.trim().is_empty()).and_then(|line| serde_json::from_str(line.trim()).ok())
+        });
+
+        let field = json.as_ref().and_then(|json| {
+            self.path.iter().try_fold(json, |value, key| value.get(key.as_str()))
+        });
+
+        match field {
+            Some(Value::String(s)) => *s == self.value,
+            Some(value) => serde_json::from_str::<Value>(&self.value).is_ok_and(|expected| expected == *value),
+            None => false
+        }
+    }
+}
+
+impl FromStr for JsonFieldCheck {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, value) = s.split_once('=').ok_or_else(|| anyhow::anyhow!("JSON field check must look like path.to.field=value"))?;
+
+        if path.is_empty() {
+            return Err(anyhow::anyhow!("JSON field path can not be empty"));
+        }
+
+        Ok(JsonFieldCheck {
+            path: path.split('.').map(|key| key.to_owned()).collect(),
+            value: value.to_owned()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn result(exit_code: i32, stdout: &str, stderr: &str) -> ExecCommandResult {
+        let mut result = crate::executor::spawn_error_result("job", "task", stderr);
+        result.exit_status = ExitStatus::from_raw(exit_code << 8);
+        result.stdout = stdout.to_owned();
+        result
+    }
+
+    fn criteria() -> SuccessCriteria {
+        SuccessCriteria { exit_codes: vec![0], stdout_pattern: None, stderr_pattern: None, json_field: None }
+    }
+
+    fn json_check(check: &str) -> SuccessCriteria {
+        SuccessCriteria { json_field: Some(check.parse().unwrap()), ..criteria() }
+    }
+
+    #[test]
+    fn exit_code_must_be_allowed() {
+        assert!(criteria().is_success(&result(0, "", "")));
+        assert!(!criteria().is_success(&result(1, "", "")));
+
+        let criteria = SuccessCriteria { exit_codes: vec![0, 3], ..criteria() };
+        assert!(criteria.is_success(&result(3, "", "")));
+        assert!(!criteria.is_success(&result(2, "", "")));
+    }
+
+    #[test]
+    fn killed_timed_out_and_aborted_commands_fail() {
+        let mut killed = result(0, "", "");
+        killed.exit_status = ExitStatus::from_raw(libc::SIGKILL);
+        assert!(!criteria().is_success(&killed));
+
+        let mut timed_out = result(0, "", "");
+        timed_out.timed_out = true;
+        assert!(!criteria().is_success(&timed_out));
+
+        let mut aborted = result(0, "", "");
+        aborted.aborted = true;
+        assert!(!criteria().is_success(&aborted));
+    }
+
+    #[test]
+    fn output_patterns_are_checked() {
+        let criteria = SuccessCriteria {
+            stdout_pattern: Some(Regex::new("^done").unwrap()),
+            stderr_pattern: Some(Regex::new("(?i)warning").unwrap()),
+            ..criteria()
+        };
+
+        assert!(criteria.is_success(&result(0, "done\n", "")));
+        assert!(!criteria.is_success(&result(0, "failed\n", "")));
+        assert!(!criteria.is_success(&result(0, "done\n", "WARNING: disk is full")));
+        assert!(!criteria.is_success(&result(1, "done\n", "")));
+    }
+
+    #[test]
+    fn json_field_is_found_in_stdout_or_its_last_line() {
+        let criteria = json_check("result.status=ok");
+
+        assert!(criteria.is_success(&result(0, r#"{"result": {"status": "ok"}}"#, "")));
+        assert!(criteria.is_success(&result(0, "{\n  \"result\": {\"status\": \"ok\"}\n}\n", "")));
+        assert!(criteria.is_success(&result(0, "starting\n{\"result\": {\"status\": \"ok\"}}\n\n", "")));
+        assert!(!criteria.is_success(&result(0, "{\"result\": {\"status\": \"ok\"}}\ndone\n", "")));
+        assert!(!criteria.is_success(&result(0, r#"{"result": {"status": "failed"}}"#, "")));
+        assert!(!criteria.is_success(&result(0, r#"{"status": "ok"}"#, "")));
+        assert!(!criteria.is_success(&result(0, "", "")));
+    }
+
+    #[test]
+    fn json_field_is_compared_as_json_value() {
+        assert!(json_check("count=3").is_success(&result(0, r#"{"count": 3}"#, "")));
+        assert!(!json_check("count=3").is_success(&result(0, r#"{"count": 4}"#, "")));
+        assert!(json_check("ok=true").is_success(&result(0, r#"{"ok": true}"#, "")));
+        assert!(json_check("error=null").is_success(&result(0, r#"{"error": null}"#, "")));
+        assert!(!json_check("ok=yes").is_success(&result(0, r#"{"ok": true}"#, "")));
+        // strings are compared as is
+        assert!(json_check("code=007").is_success(&result(0, r#"{"code": "007"}"#, "")));
+        assert!(!json_check("code=7").is_success(&result(0, r#"{"code": "007"}"#, "")));
+    }
+
+    #[test]
+    fn json_field_check_is_parsed() {
+        let check: JsonFieldCheck = "a.b=x=y".parse().unwrap();
+        assert_eq!(check.path, vec!["a", "b"]);
+        assert_eq!(check.value, "x=y");
+
+        assert!("status".parse::<JsonFieldCheck>().is_err());
+        assert!("=ok".parse::<JsonFieldCheck>().is_err());
+    }
+}
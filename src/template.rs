@@ -0,0 +1,527 @@
+use std::path::Path;
+
+use anyhow::Context as _;
+use csv::StringRecord;
+
+use crate::shell;
+
+// Command with {{...}} placeholders, compiled once and rendered for every tasks file row.
+// Placeholder is column index ({{0}}), column name from header row ({{url}}) or built-in variable
+// ({{task}}, {{task_id}}, {{attempt}}, {{worker}}), optionally followed by filters: {{url|trim|urlencode}}. {{{{ is literal {{.
+// Values are shell quoted unless raw filter is used. Placeholders inside quotes or command substitutions of template are escaped for them instead
+pub struct CommandTemplate {
+    parts: Vec<Part>
+}
+
+enum Part {
+    Text(String),
+    Placeholder { var: Var, filters: Vec<Filter>, quoting: Quoting, source: String }
+}
+
+// quotes of template text placeholder is inside of
+#[derive(Clone, Copy, PartialEq)]
+enum Quoting {
+    None,
+    Single,
+    Double
+}
+
+// shell syntax of template text
+#[derive(Clone, Copy, PartialEq)]
+enum Syntax {
+    // top level or command substitution, with number of open parentheses
+    Unquoted(usize),
+    Single,
+    Double,
+    Backtick,
+    // $'...'
+    AnsiC
+}
+
+enum Var {
+    Column(usize),
+    TaskId,
+    // known only when task is dispatched to worker
+    Attempt,
+    Worker
+}
+
+enum Filter {
+    // insert value as is, without shell quoting
+    Raw,
+    Upper,
+    Lower,
+    Trim,
+    Basename,
+    UrlEncode,
+    // JSON string literal
+    Json,
+    // used when value is empty
+    Default(String)
+}
+
+// Values of variables. Placeholders of unknown attempt and worker are kept in command as is
+pub struct Context<'a> {
+    pub record: Option<&'a StringRecord>,
+    pub task_id: &'a str,
+    pub attempt: Option<u32>,
+    pub worker: Option<usize>
+}
+
+impl Filter {
+
+    fn parse(filter: &str) -> Option<Filter> {
+        let (name, arg) = match filter.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(unquote(arg.trim()))),
+            None => (filter, None)
+        };
+
+        match (name, arg) {
+            ("raw", None) => Some(Filter::Raw),
+            ("upper", None) => Some(Filter::Upper),
+            ("lower", None) => Some(Filter::Lower),
+            ("trim", None) => Some(Filter::Trim),
+            ("basename", None) => Some(Filter::Basename),
+            ("urlencode", None) => Some(Filter::UrlEncode),
+            ("json", None) => Some(Filter::Json),
+            ("default", Some(arg)) => Some(Filter::Default(arg.to_owned())),
+            _ => None
+        }
+    }
+
+    fn apply(&self, value: String) -> String {
+        match self {
+            Filter::Raw => value,
+            Filter::Upper => value.to_uppercase(),
+            Filter::Lower => value.to_lowercase(),
+            Filter::Trim => value.trim().to_owned(),
+            Filter::Basename => Path::new(&value).file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned()),
+            Filter::UrlEncode => url_encode(&value),
+            Filter::Json => serde_json::to_string(&value).unwrap(),
+            Filter::Default(default) if value.is_empty() => default.clone(),
+            Filter::Default(_) => value
+        }
+    }
+}
+
+impl CommandTemplate {
+
+    // Fails if template references columns missing in header row or unknown filters
+    pub fn compile(template: &str, headers: Option<&StringRecord>) -> anyhow::Result<CommandTemplate> {
+        let mut unknown = vec![];
+        let mut unknown_filters = vec![];
+
+        let template_parts = parse(template, |placeholder| {
+            let mut items = split_filters(placeholder).into_iter();
+            let name = items.next().unwrap_or_default();
+
+            let filters = items.filter_map(|filter| {
+                let parsed = Filter::parse(filter);
+
+                if parsed.is_none() {
+                    unknown_filters.push(filter.to_owned());
+                }
+
+                parsed
+            }).collect();
+
+            match resolve_var(name, headers) {
+                Some(var) => Some((var, filters)),
+                None => {
+                    unknown.push(format!("{{{{{}}}}}", placeholder));
+                    None
+                }
+            }
+        }).with_context(|| format!("Invalid command {}", template))?;
+
+        if !unknown_filters.is_empty() {
+            return Err(anyhow::anyhow!("Unknown filters in command {}: {}", template, unknown_filters.join(", ")));
+        }
+
+        if !unknown.is_empty() {
+            let columns = match headers {
+                Some(headers) => headers.iter().collect::<Vec<&str>>().join(", "),
+                None => String::from("none, use --has-header to reference columns by name")
+            };
+
+            return Err(anyhow::anyhow!("Unknown placeholders in command {}: {}. Available columns: {}", template, unknown.join(", "), columns));
+        }
+
+        Ok(CommandTemplate { parts: template_parts })
+    }
+
+    pub fn render(&self, ctx: &Context) -> anyhow::Result<String> {
+        let mut command = String::new();
+
+        for part in &self.parts {
+            let (var, filters, quoting, source) = match part {
+                Part::Text(text) => {
+                    command.push_str(text);
+                    continue;
+                },
+                Part::Placeholder { var, filters, quoting, source } => (var, filters, quoting, source)
+            };
+
+            let value = match var {
+                Var::Column(idx) => {
+                    let record = ctx.record.ok_or_else(|| anyhow::anyhow!("Task {} has no columns", ctx.task_id))?;
+                    record.get(*idx).ok_or_else(|| anyhow::anyhow!("Task {} has no column {}", ctx.task_id, idx))?.to_owned()
+                },
+                Var::TaskId => ctx.task_id.to_owned(),
+                Var::Attempt | Var::Worker => {
+                    let value = match var {
+                        Var::Attempt => ctx.attempt.map(|attempt| attempt.to_string()),
+                        _ => ctx.worker.map(|worker| worker.to_string())
+                    };
+
+                    match value {
+                        Some(value) => value,
+                        None => {
+                            command.push_str(source);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let value = filters.iter().fold(value, |value, filter| filter.apply(value));
+
+            if filters.iter().any(|filter| matches!(filter, Filter::Raw)) {
+                command.push_str(&value);
+                continue;
+            }
+
+            match quoting {
+                Quoting::None => command.push_str(&shell::quote(&value)),
+                Quoting::Single => command.push_str(&shell::escape_single_quoted(&value)),
+                Quoting::Double => command.push_str(&shell::escape_double_quoted(&value))
+            }
+        }
+
+        Ok(command)
+    }
+}
+
+// split template into text and placeholders, placeholders not recognized by callback are kept as text.
+// Shell syntax of template text is tracked like sh does (quotes, command substitutions, comments and heredocs),
+// so placeholder knows how its value must be escaped. Placeholders in places where value can not be escaped are rejected
+fn parse(template: &str, mut placeholder: impl FnMut(&str) -> Option<(Var, Vec<Filter>)>) -> anyhow::Result<Vec<Part>> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut stack = vec![Syntax::Unquoted(0)];
+    // heredocs which start on the next line
+    let mut heredocs: Vec<(String, bool)> = vec![];
+    let mut prev: Option<char> = None;
+    let mut rest = template;
+
+    while let Some(c) = rest.chars().next() {
+        // escaped braces, e.g. docker inspect -f '{{{{.State.Status}}'
+        if let Some(after) = rest.strip_prefix("{{{{") {
+            text.push_str("{{");
+            prev = Some('{');
+            rest = after;
+            continue;
+        }
+
+        if rest.starts_with("{{") {
+            if let Some(end) = rest[2..].find("}}").map(|end| end + 2) {
+                let source = &rest[..end + 2];
+
+                let quoting = match stack.last().unwrap() {
+                    Syntax::Unquoted(_) => Quoting::None,
+                    Syntax::Single => Quoting::Single,
+                    Syntax::Double => Quoting::Double,
+                    Syntax::Backtick => return Err(anyhow::anyhow!("Placeholder {} inside `...` is not supported, use $(...) instead", source)),
+                    Syntax::AnsiC => return Err(anyhow::anyhow!("Placeholder {} inside $'...' is not supported, use '...' instead", source))
+                };
+
+                match placeholder(rest[2..end].trim()) {
+                    Some((var, filters)) => {
+                        if !text.is_empty() {
+                            parts.push(Part::Text(std::mem::take(&mut text)));
+                        }
+
+                        parts.push(Part::Placeholder { var, filters, quoting, source: source.to_owned() });
+                    },
+                    None => text.push_str(source)
+                }
+
+                prev = Some('}');
+                rest = &rest[end + 2..];
+                continue;
+            }
+        }
+
+        let top = *stack.last().unwrap();
+        let next = rest[c.len_utf8()..].chars().next();
+        let mut len = c.len_utf8();
+
+        match (top, c) {
+            (Syntax::Single, '\'') | (Syntax::AnsiC, '\'') | (Syntax::Double, '"') | (Syntax::Backtick, '`') => {
+                stack.pop();
+            },
+            (Syntax::Double, '\\') if matches!(next, Some('$' | '`' | '"' | '\\' | '\n')) => len += 1,
+            // escaped brace does not hide placeholder after it
+            (Syntax::Unquoted(_) | Syntax::Backtick | Syntax::AnsiC, '\\') if next.is_some_and(|next| next != '{') => len += next.unwrap().len_utf8(),
+            (Syntax::Unquoted(_) | Syntax::Double, '`') => stack.push(Syntax::Backtick),
+            (Syntax::Unquoted(_) | Syntax::Double, '$') if next == Some('(') => {
+                len += 1;
+                stack.push(Syntax::Unquoted(0));
+            },
+            (Syntax::Unquoted(_), '$') if next == Some('\'') => {
+                len += 1;
+                stack.push(Syntax::AnsiC);
+            },
+            (Syntax::Unquoted(_), '\'') => stack.push(Syntax::Single),
+            (Syntax::Unquoted(_), '"') => stack.push(Syntax::Double),
+            (Syntax::Unquoted(parens), '(') => *stack.last_mut().unwrap() = Syntax::Unquoted(parens + 1),
+            (Syntax::Unquoted(parens), ')') if parens > 0 => *stack.last_mut().unwrap() = Syntax::Unquoted(parens - 1),
+            // end of command substitution
+            (Syntax::Unquoted(_), ')') if stack.len() > 1 => {
+                stack.pop();
+            },
+            // comment lasts till the end of line, quotes inside it mean nothing
+            (Syntax::Unquoted(_), '#') if prev.is_none_or(|prev| prev.is_whitespace() || ";&|()".contains(prev)) => {
+                len = rest.find('\n').unwrap_or(rest.len());
+            },
+            (Syntax::Unquoted(_), '<') if rest.starts_with("<<") && !rest.starts_with("<<<") => {
+                len = 2;
+                heredocs.push(heredoc_delimiter(&rest[2..]));
+            },
+            (Syntax::Unquoted(_), '\n') if !heredocs.is_empty() => {
+                text.push('\n');
+                rest = &rest[1..];
+
+                // value can not be escaped inside heredoc, e.g. line with delimiter in value would end it
+                for (delimiter, strip_tabs) in heredocs.drain(..) {
+                    while !rest.is_empty() {
+                        let line = &rest[..rest.find('\n').map_or(rest.len(), |idx| idx + 1)];
+                        let unescaped = line.replace("{{{{", "");
+
+                        if unescaped.find("{{").is_some_and(|start| unescaped[start..].contains("}}")) {
+                            return Err(anyhow::anyhow!("Placeholders inside heredoc are not supported, pass value as command argument instead: {}", line.trim_end()));
+                        }
+
+                        text.push_str(&line.replace("{{{{", "{{"));
+                        rest = &rest[line.len()..];
+
+                        let content = line.trim_end_matches('\n');
+                        let content = if strip_tabs { content.trim_start_matches('\t') } else { content };
+
+                        if content == delimiter {
+                            break;
+                        }
+                    }
+                }
+
+                prev = Some('\n');
+                continue;
+            },
+            _ => {}
+        }
+
+        text.push_str(&rest[..len]);
+        prev = rest[..len].chars().last();
+        rest = &rest[len..];
+    }
+
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+
+    Ok(parts)
+}
+
+// delimiter of heredoc from text after <<, e.g. "-'EOF'" and whether leading tabs are stripped from its lines
+fn heredoc_delimiter(text: &str) -> (String, bool) {
+    let strip_tabs = text.starts_with('-');
+    let text = text.strip_prefix('-').unwrap_or(text).trim_start_matches([' ', '\t']);
+
+    let delimiter = text.chars()
+        .take_while(|c| !c.is_whitespace() && !";&|<>()".contains(*c))
+        .filter(|c| !"'\"\\".contains(*c))
+        .collect();
+
+    (delimiter, strip_tabs)
+}
+
+fn resolve_var(name: &str, headers: Option<&StringRecord>) -> Option<Var> {
+    if let Ok(idx) = name.parse::<usize>() {
+        return Some(Var::Column(idx));
+    }
+
+    // columns take precedence over built-in variables
+    if let Some(idx) = headers.and_then(|headers| headers.iter().position(|header| header.trim() == name)) {
+        return Some(Var::Column(idx));
+    }
+
+    match name {
+        // for backward compatibility
+        "task" => Some(Var::Column(0)),
+        "task_id" => Some(Var::TaskId),
+        "attempt" => Some(Var::Attempt),
+        "worker" => Some(Var::Worker),
+        _ => None
+    }
+}
+
+// split placeholder by '|' which are not inside quotes, e.g. "name|default:'a|b'"
+fn split_filters(placeholder: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+
+    for (idx, c) in placeholder.char_indices() {
+        match (c, quote) {
+            ('\'', None) | ('"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('|', None) => {
+                items.push(placeholder[start..idx].trim());
+                start = idx + 1;
+            },
+            _ => {}
+        }
+    }
+
+    items.push(placeholder[start..].trim());
+    items
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in &['\'', '"'] {
+        if value.len() >= 2 && value.starts_with(*quote) && value.ends_with(*quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+
+    value
+}
+
+// percent-encode everything except unreserved characters (RFC 3986)
+fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn render(template: &str, row: &[&str]) -> String {
+        let record = StringRecord::from(row.to_vec());
+        let ctx = Context { record: Some(&record), task_id: row[0], attempt: Some(2), worker: Some(3) };
+        CommandTemplate::compile(template, None).unwrap().render(&ctx).unwrap()
+    }
+
+    fn sh_output(command: &str) -> String {
+        let output = Command::new("sh").arg("-c").arg(command).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn renders_placeholders_next_to_text() {
+        assert_eq!(render("cp /in/{{0}}.csv /out/{{1}}x", &["a", "b c"]), "cp /in/a.csv /out/'b c'x");
+        assert_eq!(render("{{0}}{{1}}", &["a", "b"]), "ab");
+        assert_eq!(render("echo {{ 1 | upper }}", &["a", "b"]), "echo B");
+        assert_eq!(render("echo {{attempt}}-{{worker}} {{task_id}}", &["a"]), "echo 2-3 a");
+    }
+
+    #[test]
+    fn values_can_not_escape_from_template_quotes() {
+        let value = "x;touch pwned '\"$(id)`id`\\";
+
+        for template in &["printf %s '{{0}}'", "printf %s \"{{0}}\"", "printf %s {{0}}", "printf %s 'a'{{0}}\"b\""] {
+            let expected = if template.ends_with("\"b\"") { format!("a{}b", value) } else { value.to_owned() };
+            assert_eq!(sh_output(&render(template, &[value])), expected, "template {}", template);
+        }
+    }
+
+    #[test]
+    fn values_can_not_escape_from_command_substitutions() {
+        let value = "x;touch pwned '\"$(id)`id`\\";
+
+        let templates = [
+            "printf %s \"$(printf %s {{0}})\"",
+            "printf %s \"$(printf %s \"{{0}}\")\"",
+            "printf %s \"$(printf %s \"$(printf %s '{{0}}')\")\"",
+            "printf %s \"$( (printf %s {{0}}) )\"",
+            "printf %s \"$(printf %s {{0}})$((1 + 1))\" | head -c -1"
+        ];
+
+        for template in &templates {
+            assert_eq!(sh_output(&render(template, &[value])), value, "template {}", template);
+        }
+
+        // quotes after substitution are tracked again
+        assert_eq!(sh_output(&render("printf %s \"$(printf a)\"'{{0}}'", &[value])), format!("a{}", value));
+    }
+
+    #[test]
+    fn quotes_inside_comments_are_ignored() {
+        let value = "x;touch pwned '";
+        assert_eq!(sh_output(&render("# it's comment\nprintf %s {{0}}", &[value])), value);
+        assert_eq!(sh_output(&render("printf %s {{0}} # it's comment", &[value])), value);
+    }
+
+    #[test]
+    fn rejects_placeholders_which_can_not_be_escaped() {
+        assert!(CommandTemplate::compile("cat <<EOF\n{{0}}\nEOF", None).is_err());
+        assert!(CommandTemplate::compile("cat <<-'EOF'\n\ta {{0}}\n\tEOF", None).is_err());
+        assert!(CommandTemplate::compile("echo `echo {{0}}`", None).is_err());
+        assert!(CommandTemplate::compile("echo \"`echo {{0}}`\"", None).is_err());
+        assert!(CommandTemplate::compile("echo $'{{0}}'", None).is_err());
+    }
+
+    #[test]
+    fn placeholders_after_heredoc_are_escaped() {
+        let value = "x;touch pwned '";
+        let output = sh_output(&render("cat <<EOF; printf %s {{0}}\n'line\nEOF\nprintf %s {{0}}", &[value]));
+
+        assert_eq!(output, format!("'line\n{}{}", value, value));
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_change_quoting() {
+        assert_eq!(render(r#"echo \'{{0}}"#, &["a b"]), r#"echo \''a b'"#);
+        assert_eq!(render(r#"echo "\"{{0}}""#, &["a$b"]), r#"echo "\"a\$b""#);
+        // backslash is literal inside single quotes
+        assert_eq!(render(r#"echo '\'{{0}}"#, &["a b"]), r#"echo '\''a b'"#);
+    }
+
+    #[test]
+    fn escaped_braces_are_literal() {
+        assert_eq!(render("docker inspect -f '{{{{.State.Status}}' {{0}}", &["web 1"]), "docker inspect -f '{{.State.Status}}' 'web 1'");
+        assert_eq!(render("echo {{{{{{0}}", &["a"]), "echo {{a");
+        assert!(CommandTemplate::compile("docker inspect -f '{{.State.Status}}'", None).is_err());
+    }
+
+    #[test]
+    fn raw_filter_inserts_value_as_is() {
+        assert_eq!(render("ls {{0|raw}} '{{0|raw}}'", &["-l -a"]), "ls -l -a '-l -a'");
+    }
+
+    #[test]
+    fn applies_filters_and_defaults() {
+        assert_eq!(render("echo {{0|trim|upper}} {{1|default:'n/a'}} {{1|default:\"a|b\"}}", &[" a ", ""]), "echo A n/a 'a|b'");
+        assert_eq!(render("echo {{0|basename}} {{0|urlencode}}", &["/x/a b"]), "echo 'a b' %2Fx%2Fa%20b");
+    }
+
+    #[test]
+    fn reports_unknown_placeholders_and_filters() {
+        assert!(CommandTemplate::compile("echo {{name}}", None).is_err());
+        assert!(CommandTemplate::compile("echo {{0|bogus}}", None).is_err());
+
+        let headers = StringRecord::from(vec!["id", "name"]);
+        assert!(CommandTemplate::compile("echo {{name}}", Some(&headers)).is_ok());
+    }
+}
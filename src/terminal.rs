@@ -1,13 +1,13 @@
 use std::io::{self, Stdout};
-use tui::layout::{Margin, Rect};
+use tui::layout::{Constraint, Margin, Rect};
 use tui::style::{Color, Modifier, Style};
-use tui::text::{Span, Spans};
-use tui::widgets::{Borders, Gauge, Paragraph};
+use tui::text::{Span, Spans, Text};
+use tui::widgets::{Borders, Cell, Clear, Gauge, Paragraph, Row, Table, TableState, Wrap};
 use tui::{Terminal, widgets::Block};
 use tui::backend::TermionBackend;
 use termion::raw::{IntoRawMode, RawTerminal};
 
-use crate::storage::TaskStatsResult;
+use crate::storage::{TaskDetails, TaskListItem, TaskStatsResult, TaskStatus};
 
 pub struct TerminalUi {
     terminal: Terminal<TermionBackend<RawTerminal<Stdout>>>
@@ -21,7 +21,7 @@ impl TerminalUi {
         let terminal = Terminal::new(backend)?;
         
         Ok(TerminalUi{
-            terminal: terminal
+            terminal
         })
     }
 
@@ -34,12 +34,24 @@ impl TerminalUi {
             f.render_widget(block, size);
 
             let size = size.inner(&Margin { horizontal: 2, vertical: 2 });
-            let w_status_text = Paragraph::new("Status: ".to_owned() + data.log_message.as_str());
+            let w_status_text = Paragraph::new(vec![
+                Spans::from("Status: ".to_owned() + data.log_message.as_str()),
+                Spans::from(vec![
+                    Span::raw(format!("Workers: {} | Scheduling: ", data.workers)),
+                    if data.paused {
+                        Span::styled("paused", Style::default().fg(Color::Yellow))
+                    } else {
+                        Span::raw("running")
+                    },
+                    Span::raw(" | p pause/resume, +/- workers, r retry failed, a abort selected task, o follow output, q quit")
+                ])
+            ]);
             f.render_widget(w_status_text, size);
 
         
             // render tasks stats
-            let size = Rect::new(size.x, size.y + 2, size.width, size.height - 2);
+            let size = rows_below(size, 2);
+            let details_area = size;
            
             let avg_elapsed_time = if data.processed_tasks_count > 0 {
                 data.total_elapsed_time / data.processed_tasks_count as u128
@@ -72,6 +84,12 @@ impl TerminalUi {
                 Spans::from(vec![
                     Span::styled(format!("aborted:     {}", data.tasks_stats_struct.aborted), Style::default().fg(Color::LightMagenta))
                 ]),
+                Spans::from(vec![
+                    Span::styled(format!("timeout:     {}", data.tasks_stats_struct.timeout), Style::default().fg(Color::LightRed))
+                ]),
+                Spans::from(vec![
+                    Span::styled(format!("skipped:     {}", data.tasks_stats_struct.skipped), Style::default().fg(Color::DarkGray))
+                ]),
                 Spans::from(vec![
                     Span::raw(""),
                 ]),
@@ -90,15 +108,14 @@ impl TerminalUi {
             f.render_widget(w_tasks_status, size);
 
             // render progress bar
-            let size = Rect::new(size.x, size.y + 14, size.width, size.height - 14);
+            let size = rows_below(size, 16);
             {
-                let size = Rect::new(size.x, size.y, size.width, 1);
+                let size = Rect::new(size.x, size.y, size.width, size.height.min(1));
 
                 let progress = if data.tasks_stats_struct.total > 0 {
-                    let num_of_finished_jobs = data.tasks_stats_struct.completed + data.tasks_stats_struct.error;
+                    let num_of_finished_jobs = data.tasks_stats_struct.completed + data.tasks_stats_struct.error + data.tasks_stats_struct.timeout + data.tasks_stats_struct.skipped;
                     let tmp = (num_of_finished_jobs as f64 / data.tasks_stats_struct.total as f64) * 100.0;
-                    let tmp = tmp as u16;
-                    tmp
+                    tmp as u16
                 } else {
                     0
                 };
@@ -109,6 +126,96 @@ impl TerminalUi {
                 
                 f.render_widget(w_total_progress, size);
             }
+
+            // render tasks list
+            let size = rows_below(size, 2);
+            {
+                let title = match &data.task_filter {
+                    Some(status) => format!("Tasks: {} (Up/Down select, Enter details, f filter)", status),
+                    None => String::from("Tasks: running and recently finished (Up/Down select, Enter details, f filter)")
+                };
+
+                let rows = data.task_list.iter().map(|task| {
+                    let mut cells = vec![
+                        Cell::from(task.task_id.clone()),
+                        Cell::from(task.status.to_string()).style(status_style(&task.status)),
+                        Cell::from(task.attempt.to_string()),
+                        Cell::from(task.elapsed_ms.map_or("-".to_owned(), |ms| ms.to_string()))
+                    ];
+
+                    if !data.stage_names.is_empty() {
+                        cells.insert(1, Cell::from(data.stage_names.get(task.stage).cloned().unwrap_or_default()));
+                    }
+
+                    Row::new(cells)
+                });
+
+                let (header, widths) = if data.stage_names.is_empty() {
+                    (
+                        vec!["Task", "Status", "Attempt", "Elapsed (ms)"],
+                        vec![Constraint::Percentage(55), Constraint::Percentage(15), Constraint::Percentage(10), Constraint::Percentage(20)]
+                    )
+                } else {
+                    (
+                        vec!["Task", "Stage", "Status", "Attempt", "Elapsed (ms)"],
+                        vec![Constraint::Percentage(40), Constraint::Percentage(15), Constraint::Percentage(15), Constraint::Percentage(10), Constraint::Percentage(20)]
+                    )
+                };
+
+                let w_tasks_list = Table::new(rows)
+                    .header(Row::new(header).style(Style::default().add_modifier(Modifier::BOLD)))
+                    .block(Block::default().title(title).borders(Borders::TOP))
+                    .widths(&widths)
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+                let mut state = TableState::default();
+                if !data.task_list.is_empty() {
+                    state.select(Some(data.selected_task));
+                }
+
+                f.render_stateful_widget(w_tasks_list, size, &mut state);
+            }
+
+            // render task details over stats and tasks list
+            if let Some(details) = &data.task_details {
+                let mut text = Text::from(vec![
+                    Spans::from(format!("Status:    {}", details.status)),
+                    Spans::from(format!("Exit code: {}", details.exit_code.map_or("-".to_owned(), |c| c.to_string()))),
+                    Spans::from(format!("Command:   {}", details.command.as_deref().unwrap_or(""))),
+                    Spans::from(format!("Output:    {} {}", details.stdout_path.as_deref().unwrap_or("-"), details.stderr_path.as_deref().unwrap_or(""))),
+                    Spans::from(""),
+                    Spans::from(Span::styled("Stdout:", Style::default().add_modifier(Modifier::BOLD)))
+                ]);
+                text.extend(Text::raw(details.stdout.clone().unwrap_or_default()));
+                text.extend(Text::from(Spans::from(Span::styled("Stderr:", Style::default().add_modifier(Modifier::BOLD)))));
+                text.extend(Text::raw(details.stderr.clone().unwrap_or_default()));
+
+                let w_details = Paragraph::new(text)
+                    .block(Block::default().title(format!("Task {} (Up/Down scroll, Esc close)", details.task_id)).borders(Borders::ALL))
+                    .wrap(Wrap { trim: false })
+                    .scroll((data.details_scroll, 0));
+
+                f.render_widget(Clear, details_area);
+                f.render_widget(w_details, details_area);
+            }
+
+            // render live output of followed task, newest lines at the bottom
+            if let Some(task_id) = &data.follow_task {
+                let text = match &data.follow_output {
+                    Some(output) => {
+                        let visible = details_area.height.saturating_sub(2) as usize;
+                        let lines: Vec<&str> = output.lines().collect();
+                        lines[lines.len().saturating_sub(visible)..].join("\n")
+                    },
+                    None => String::from("Task is not running. Press Enter to see stored output")
+                };
+
+                let w_follow = Paragraph::new(text)
+                    .block(Block::default().title(format!("Output of task {} (Esc close)", task_id)).borders(Borders::ALL));
+
+                f.render_widget(Clear, details_area);
+                f.render_widget(w_follow, details_area);
+            }
         }).unwrap();
     }
 
@@ -117,25 +224,82 @@ impl TerminalUi {
     }
 }
 
+// part of area below given number of rows, empty if terminal is too small
+fn rows_below(area: Rect, rows: u16) -> Rect {
+    let rows = rows.min(area.height);
+    Rect::new(area.x, area.y + rows, area.width, area.height - rows)
+}
+
+fn status_style(status: &TaskStatus) -> Style {
+    match status {
+        TaskStatus::Completed => Style::default().fg(Color::LightGreen),
+        TaskStatus::Resheduled => Style::default().fg(Color::Yellow),
+        TaskStatus::Error => Style::default().fg(Color::Red),
+        TaskStatus::Timeout => Style::default().fg(Color::LightRed),
+        TaskStatus::Aborted => Style::default().fg(Color::LightMagenta),
+        TaskStatus::Skipped => Style::default().fg(Color::DarkGray),
+        _ => Style::default()
+    }
+}
+
+#[derive(Default)]
 pub struct LayoutData {
     pub log_message: String,
     pub tasks_stats_struct: TaskStatsResult,
     pub processed_tasks_count: u64,
     pub total_elapsed_time: u128,
     pub min_elapsed_time: Option<u128>,
-    pub max_elapsed_time: Option<u128>
+    pub max_elapsed_time: Option<u128>,
+    pub workers: usize,
+    pub paused: bool,
+    pub task_list: Vec<TaskListItem>,
+    pub selected_task: usize,
+    pub task_filter: Option<TaskStatus>,
+    pub task_details: Option<TaskDetails>,
+    pub details_scroll: u16,
+    // task which output is followed while it is running
+    pub follow_task: Option<String>,
+    pub follow_output: Option<String>,
+    // names of pipeline stages, empty if there is single command
+    pub stage_names: Vec<String>
 }
 
-impl Default for LayoutData {
-    
-    fn default() -> Self {
-        Self {
-            log_message: Default::default(),
-            tasks_stats_struct: Default::default(),
-            processed_tasks_count: Default::default(),
-            total_elapsed_time: Default::default(),
-            min_elapsed_time: None,
-            max_elapsed_time: None,
+// statuses which can be selected in tasks list filter, None shows running and recently finished tasks
+const TASK_FILTERS: &[Option<TaskStatus>] = &[
+    None,
+    Some(TaskStatus::Processing),
+    Some(TaskStatus::Completed),
+    Some(TaskStatus::Error),
+    Some(TaskStatus::Timeout),
+    Some(TaskStatus::Resheduled),
+    Some(TaskStatus::Aborted),
+    Some(TaskStatus::Skipped),
+    Some(TaskStatus::New)
+];
+
+impl LayoutData {
+
+    pub fn select_task(&mut self, offset: isize) {
+        if self.task_list.is_empty() {
+            self.selected_task = 0;
+            return;
         }
+
+        let max = self.task_list.len() as isize - 1;
+        self.selected_task = (self.selected_task as isize + offset).clamp(0, max) as usize;
+    }
+
+    pub fn selected_task_id(&self) -> Option<&str> {
+        self.task_list.get(self.selected_task).map(|task| task.task_id.as_str())
+    }
+
+    pub fn next_task_filter(&mut self) {
+        let idx = TASK_FILTERS.iter().position(|f| *f == self.task_filter).unwrap_or(0);
+        self.task_filter = TASK_FILTERS[(idx + 1) % TASK_FILTERS.len()];
+        self.selected_task = 0;
+    }
+
+    pub fn scroll_details(&mut self, offset: i32) {
+        self.details_scroll = (self.details_scroll as i32 + offset).max(0) as u16;
     }
 }
\ No newline at end of file
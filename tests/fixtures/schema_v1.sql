@@ -0,0 +1,17 @@
+-- database created by workman 0.5.2 (no user_version)
+CREATE TABLE tasks (
+     task_id VARCHAR(255) primary key,
+     status VARCHAR(255) not null,
+     command TEXT,
+     elapsed_time VARCHAR(255),
+     reshedule_count INT not null,
+     ignore_till INT null,
+     stdout TEXT,
+     stderr TEXT
+ );
+
+INSERT INTO tasks VALUES ('1', 'completed', 'echo 1', '12', 0, NULL, '1
+', '');
+INSERT INTO tasks VALUES ('2', 'rescheduled', 'echo 2; exit 1', '15', 1, 1626000000, '2
+', '');
+INSERT INTO tasks VALUES ('3', 'new', 'echo 3', NULL, 0, NULL, NULL, NULL);
@@ -0,0 +1,55 @@
+-- database created by workman with schema version 2
+CREATE TABLE tasks (
+     task_id VARCHAR(255) primary key,
+     status VARCHAR(255) not null,
+     command TEXT,
+     elapsed_time VARCHAR(255),
+     reshedule_count INT not null,
+     ignore_till INT null,
+     stdout TEXT,
+     stderr TEXT,
+     timeout INT null,
+     run_id INT null
+ );
+
+CREATE TABLE settings (
+     name VARCHAR(255) primary key,
+     value TEXT
+ );
+
+CREATE TABLE runs (
+     id INTEGER primary key autoincrement,
+     command VARCHAR(255) not null,
+     config TEXT not null,
+     host VARCHAR(255),
+     started_at INT not null,
+     finished_at INT null,
+     exit_reason VARCHAR(255) null
+ );
+
+CREATE TABLE attempts (
+     id INTEGER primary key autoincrement,
+     task_id VARCHAR(255) not null,
+     run_id INT null,
+     attempt INT not null,
+     worker_id INT,
+     started_at INT not null,
+     finished_at INT not null,
+     exit_code INT null,
+     signal INT null,
+     elapsed_ms INT not null,
+     stdout TEXT,
+     stderr TEXT
+ );
+
+CREATE INDEX attempts_task_id ON attempts (task_id);
+
+INSERT INTO settings VALUES ('run_config', '{"workers":2,"tries":0,"retry_delay":1,"timeout":5,"shutdown_mode":"terminate","grace_period":10,"exec":"echo {{task}}","delimeter":",","has_header":false,"timeout_column":null}');
+INSERT INTO runs VALUES (1, 'process', '{"workers":2}', 'host', 1626000000, 1626000010, 'completed');
+INSERT INTO tasks VALUES ('a', 'completed', 'echo a', '2010', 0, NULL, 'a
+', '', 5, 1);
+INSERT INTO tasks VALUES ('b', 'new', 'echo b', NULL, 0, NULL, NULL, NULL, NULL, NULL);
+INSERT INTO attempts VALUES (1, 'a', 1, 1, 1, 1626000001, 1626000003, 0, NULL, 2010, 'a
+', '');
+
+PRAGMA user_version = 2;
@@ -0,0 +1,77 @@
+-- database created by workman with schema version 7
+CREATE TABLE tasks (
+     task_id VARCHAR(255) primary key,
+     status VARCHAR(255) not null,
+     command TEXT,
+     reshedule_count INT not null,
+     ignore_till INT null,
+     stdout TEXT,
+     stderr TEXT,
+     timeout INT null,
+     run_id INT null,
+     exit_code INT null,
+     signal INT null,
+     elapsed_ms INT null,
+     created_at INT null,
+     started_at INT null,
+     finished_at INT null,
+     stdout_path TEXT null,
+     stderr_path TEXT null,
+     priority INT not null default 0,
+     stage INT not null default 0,
+     stage_commands TEXT null
+ );
+
+CREATE INDEX tasks_status_priority ON tasks (status, priority);
+
+CREATE TABLE settings (
+     name VARCHAR(255) primary key,
+     value TEXT
+ );
+
+CREATE TABLE runs (
+     id INTEGER primary key autoincrement,
+     command VARCHAR(255) not null,
+     config TEXT not null,
+     host VARCHAR(255),
+     started_at INT not null,
+     finished_at INT null,
+     exit_reason VARCHAR(255) null
+ );
+
+CREATE TABLE attempts (
+     id INTEGER primary key autoincrement,
+     task_id VARCHAR(255) not null,
+     run_id INT null,
+     attempt INT not null,
+     worker_id INT,
+     started_at INT not null,
+     finished_at INT not null,
+     exit_code INT null,
+     signal INT null,
+     elapsed_ms INT not null,
+     stdout TEXT,
+     stderr TEXT
+ );
+
+CREATE INDEX attempts_task_id ON attempts (task_id);
+
+CREATE TABLE task_deps (
+     task_id VARCHAR(255) not null,
+     depends_on VARCHAR(255) not null,
+     primary key (task_id, depends_on)
+ );
+
+CREATE INDEX task_deps_depends_on ON task_deps (depends_on);
+
+INSERT INTO settings VALUES ('run_config', '{"workers":2,"tries":0,"retry_delay":1,"timeout":5,"shutdown_mode":"terminate","grace_period":10,"exec":"echo {{task}}","delimeter":",","has_header":false,"timeout_column":null}');
+INSERT INTO runs VALUES (1, 'process', '{"workers":2}', 'host', 1626000000, 1626000010, 'completed');
+INSERT INTO tasks VALUES ('a', 'completed', 'echo a', 0, NULL, 'a
+', '', 5, 1, 0, NULL, 2010, 1626000000, 1626000001, 1626000003, NULL, NULL, 0, 1, '["true","echo a"]');
+INSERT INTO tasks VALUES ('b', 'new', 'echo b', 0, NULL, NULL, NULL, NULL, NULL, NULL, NULL, NULL, 1626000000, NULL, NULL, NULL, NULL, 5, 0, NULL);
+INSERT INTO attempts VALUES (1, 'a', 1, 1, 1, 1626000001, 1626000003, 0, NULL, 2010, 'a
+', '');
+
+INSERT INTO task_deps VALUES ('b', 'a');
+
+PRAGMA user_version = 7;